@@ -0,0 +1,232 @@
+; LC3 operating system bundled with the simulator.
+;
+; Provides the standard trap service routines (GETC, OUT, PUTS, IN, PUTSP,
+; HALT) on top of the keyboard, display, and machine control registers, plus
+; handlers for the exceptions the CPU can raise.  Every routine runs in
+; supervisor mode and returns to the user program with RTI, so the user's
+; registers (other than R0 for GETC/IN, and R7) and condition codes are kept.
+
+        .ORIG x0000
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; TRAP VECTOR TABLE (x0000 - x00FF)
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+        .BLKW x20
+        .FILL TRAP_GETC         ; x20
+        .FILL TRAP_OUT          ; x21
+        .FILL TRAP_PUTS         ; x22
+        .FILL TRAP_IN           ; x23
+        .FILL TRAP_PUTSP        ; x24
+        .FILL TRAP_HALT         ; x25
+        .BLKW xDA
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; INTERRUPT VECTOR TABLE (x0100 - x01FF)
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+        .FILL EXC_PRIVILEGE     ; x00 privilege mode violation
+        .FILL EXC_ILLEGAL       ; x01 illegal opcode
+        .BLKW xFE
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; TRAP SERVICE ROUTINES (x0200 onwards)
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+; GETC: reads one character from the keyboard into R0 without echoing it.
+TRAP_GETC
+        ST R7, GETC_SAVE_R7
+        JSR READ_CHAR
+        LD R7, GETC_SAVE_R7
+        RTI
+
+; OUT: writes the character in R0 to the display.
+TRAP_OUT
+        ST R7, OUT_SAVE_R7
+        JSR WRITE_CHAR
+        LD R7, OUT_SAVE_R7
+        RTI
+
+; PUTS: writes the string starting at R0 (one character per word).
+TRAP_PUTS
+        ST R7, PUTS_SAVE_R7
+        JSR WRITE_STRING
+        LD R7, PUTS_SAVE_R7
+        RTI
+
+; IN: prompts for a character, echoes it, and leaves it in R0.
+TRAP_IN
+        ST R7, IN_SAVE_R7
+        LEA R0, IN_PROMPT
+        JSR WRITE_STRING
+        JSR READ_CHAR
+        JSR WRITE_CHAR
+        ST R0, IN_SAVE_R0
+        LD R0, NEWLINE
+        JSR WRITE_CHAR
+        LD R0, IN_SAVE_R0
+        LD R7, IN_SAVE_R7
+        RTI
+
+; PUTSP: writes the packed string starting at R0 (two characters per word,
+; low byte first) up to the first zero byte.
+TRAP_PUTSP
+        ST R0, PUTSP_SAVE_R0
+        ST R1, PUTSP_SAVE_R1
+        ST R2, PUTSP_SAVE_R2
+        ST R3, PUTSP_SAVE_R3
+        ST R7, PUTSP_SAVE_R7
+        ADD R1, R0, #0
+PUTSP_LOOP
+        LDR R2, R1, #0
+        LD R3, LOW_BYTE_MASK
+        AND R0, R2, R3
+        BRz PUTSP_DONE
+        JSR WRITE_CHAR
+
+        ; shift the high byte down by moving its 8 bits into R0 one at a time
+        AND R0, R0, #0
+        AND R3, R3, #0
+        ADD R3, R3, #8
+PUTSP_SHIFT
+        ADD R0, R0, R0
+        ADD R2, R2, #0
+        BRzp PUTSP_NEXT_BIT
+        ADD R0, R0, #1
+PUTSP_NEXT_BIT
+        ADD R2, R2, R2
+        ADD R3, R3, #-1
+        BRp PUTSP_SHIFT
+
+        ADD R0, R0, #0
+        BRz PUTSP_DONE
+        JSR WRITE_CHAR
+        ADD R1, R1, #1
+        BRnzp PUTSP_LOOP
+PUTSP_DONE
+        LD R0, PUTSP_SAVE_R0
+        LD R1, PUTSP_SAVE_R1
+        LD R2, PUTSP_SAVE_R2
+        LD R3, PUTSP_SAVE_R3
+        LD R7, PUTSP_SAVE_R7
+        RTI
+
+; HALT: stops the machine by clearing the clock enable bit of the MCR.  If the
+; machine is restarted, execution carries on after the HALT.
+TRAP_HALT
+        ST R0, HALT_SAVE_R0
+        ST R7, HALT_SAVE_R7
+        LEA R0, HALT_MESSAGE
+        JSR WRITE_STRING
+        JSR STOP_CLOCK
+        LD R0, HALT_SAVE_R0
+        LD R7, HALT_SAVE_R7
+        RTI
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; EXCEPTION HANDLERS
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+; There is no sensible way to resume after an exception, so report it and
+; keep the machine stopped.
+EXC_PRIVILEGE
+        LEA R0, PRIVILEGE_MESSAGE
+        BRnzp EXC_REPORT
+EXC_ILLEGAL
+        LEA R0, ILLEGAL_MESSAGE
+EXC_REPORT
+        JSR WRITE_STRING
+EXC_STOP
+        JSR STOP_CLOCK
+        BRnzp EXC_STOP
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; DEVICE SUBROUTINES
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+; Waits for a key and returns it in R0.
+READ_CHAR
+        LDI R0, OS_KBSR
+        BRzp READ_CHAR
+        LDI R0, OS_KBDR
+        RET
+
+; Waits for the display and writes the character in R0 to it.
+WRITE_CHAR
+        ST R1, WRITE_CHAR_SAVE_R1
+WRITE_CHAR_WAIT
+        LDI R1, OS_DSR
+        BRzp WRITE_CHAR_WAIT
+        STI R0, OS_DDR
+        LD R1, WRITE_CHAR_SAVE_R1
+        RET
+
+; Writes the string starting at R0 (one character per word).
+WRITE_STRING
+        ST R0, WRITE_STRING_SAVE_R0
+        ST R1, WRITE_STRING_SAVE_R1
+        ST R7, WRITE_STRING_SAVE_R7
+        ADD R1, R0, #0
+WRITE_STRING_LOOP
+        LDR R0, R1, #0
+        BRz WRITE_STRING_DONE
+        JSR WRITE_CHAR
+        ADD R1, R1, #1
+        BRnzp WRITE_STRING_LOOP
+WRITE_STRING_DONE
+        LD R0, WRITE_STRING_SAVE_R0
+        LD R1, WRITE_STRING_SAVE_R1
+        LD R7, WRITE_STRING_SAVE_R7
+        RET
+
+; Clears MCR[15], which stops the machine after the current instruction.
+STOP_CLOCK
+        ST R0, STOP_CLOCK_SAVE_R0
+        ST R1, STOP_CLOCK_SAVE_R1
+        LDI R0, OS_MCR
+        LD R1, CLOCK_DISABLE_MASK
+        AND R0, R0, R1
+        STI R0, OS_MCR
+        LD R0, STOP_CLOCK_SAVE_R0
+        LD R1, STOP_CLOCK_SAVE_R1
+        RET
+
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+; DATA
+;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
+
+OS_KBSR             .FILL xFE00
+OS_KBDR             .FILL xFE02
+OS_DSR              .FILL xFE04
+OS_DDR              .FILL xFE06
+OS_MCR              .FILL xFFFE
+
+CLOCK_DISABLE_MASK  .FILL x7FFF
+LOW_BYTE_MASK       .FILL x00FF
+NEWLINE             .FILL x000A
+
+GETC_SAVE_R7        .BLKW 1
+OUT_SAVE_R7         .BLKW 1
+PUTS_SAVE_R7        .BLKW 1
+IN_SAVE_R0          .BLKW 1
+IN_SAVE_R7          .BLKW 1
+PUTSP_SAVE_R0       .BLKW 1
+PUTSP_SAVE_R1       .BLKW 1
+PUTSP_SAVE_R2       .BLKW 1
+PUTSP_SAVE_R3       .BLKW 1
+PUTSP_SAVE_R7       .BLKW 1
+HALT_SAVE_R0        .BLKW 1
+HALT_SAVE_R7        .BLKW 1
+WRITE_CHAR_SAVE_R1  .BLKW 1
+WRITE_STRING_SAVE_R0 .BLKW 1
+WRITE_STRING_SAVE_R1 .BLKW 1
+WRITE_STRING_SAVE_R7 .BLKW 1
+STOP_CLOCK_SAVE_R0  .BLKW 1
+STOP_CLOCK_SAVE_R1  .BLKW 1
+
+IN_PROMPT           .STRINGZ "\nInput a character> "
+HALT_MESSAGE        .STRINGZ "\n\n--- Halting the LC-3 ---\n\n"
+PRIVILEGE_MESSAGE   .STRINGZ "\n\n--- Privilege mode violation ---\n\n"
+ILLEGAL_MESSAGE     .STRINGZ "\n\n--- Illegal opcode ---\n\n"
+
+        .END
@@ -0,0 +1,476 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use cpu::{Address, Instruction};
+use errors::Failable;
+use errors::LC3Error;
+use program::Program;
+
+//////////////////////////////////////////////////////
+// LC3 ASSEMBLER
+//////////////////////////////////////////////////////
+
+// Two-pass assembler for LC3 assembly source.  The first pass assigns an
+// address to every line and records labels, the second pass encodes each
+// statement now that every label's address is known.
+
+pub type SymbolTable = BTreeMap<String, Address>;
+
+// The source line each word of the program was assembled from.
+pub type SourceMap = BTreeMap<Address, usize>;
+
+pub struct Assembly {
+    pub program: Program,
+    pub symbols: SymbolTable,
+    pub source_map: SourceMap
+}
+
+pub fn assemble_file<P: AsRef<Path>>(filename: P) -> Failable<Assembly> {
+    let source = fs::read_to_string(filename)?;
+    assemble(&source)
+}
+
+pub fn assemble(source: &str) -> Failable<Assembly> {
+    let (origin, statements, symbols) = first_pass(source)?;
+
+    let mut instructions = Vec::new();
+    let mut source_map = SourceMap::new();
+    for statement in &statements {
+        let start = instructions.len();
+
+        match statement.kind {
+            StatementKind::Operation(ref mnemonic, ref operands) => {
+                let instr = encode(mnemonic, operands, statement.address, &symbols)
+                    .map_err(|reason| LC3Error::AssemblyError(statement.line, reason))?;
+                instructions.push(instr);
+            },
+            StatementKind::Fill(ref operand) => {
+                let value = fill_value(operand, &symbols)
+                    .map_err(|reason| LC3Error::AssemblyError(statement.line, reason))?;
+                instructions.push(value);
+            },
+            StatementKind::Data(ref words) => {
+                instructions.extend(words);
+            }
+        }
+
+        for offset in 0..instructions.len() - start {
+            source_map.insert(statement.address.wrapping_add(offset as Address), statement.line);
+        }
+    }
+
+    Ok(Assembly {
+        program: Program::new(origin, instructions),
+        symbols,
+        source_map
+    })
+}
+
+
+//////////////////////////////////////////////////////
+// FIRST PASS (addresses and labels)
+//////////////////////////////////////////////////////
+
+
+struct Statement {
+    line: usize,
+    address: Address,
+    kind: StatementKind
+}
+
+enum StatementKind {
+    Operation(String, Vec<Token>),
+    Fill(Token),
+    Data(Vec<Instruction>)
+}
+
+fn first_pass(source: &str) -> Failable<(Address, Vec<Statement>, SymbolTable)> {
+    let mut origin: Option<Address> = None;
+    let mut address: u32 = 0;
+    let mut statements = Vec::new();
+    let mut symbols = SymbolTable::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |reason: String| LC3Error::AssemblyError(line, reason);
+
+        let mut tokens = tokenize(text).map_err(&error)?.into_iter().peekable();
+
+        // a leading word that isn't an opcode or directive is a label
+        let label = match tokens.peek() {
+            Some(Token::Word(word)) if !is_mnemonic(word) && !word.starts_with('.') => {
+                Some(word.trim_end_matches(':').to_owned())
+            },
+            _ => None
+        };
+
+        if let Some(label) = label {
+            tokens.next();
+            if origin.is_none() { return Err(error(format!("label {} appears before .ORIG", label))) }
+            if !is_valid_label(&label) { return Err(error(format!("{} is not a valid label", label))) }
+            if symbols.contains_key(&label) { return Err(error(format!("duplicate label {}", label))) }
+            symbols.insert(label, address as Address);
+        }
+
+        let op = match tokens.next() {
+            Some(Token::Word(word)) => word.to_uppercase(),
+            Some(Token::Str(_)) => return Err(error("unexpected string".to_owned())),
+            None => continue
+        };
+        let operands: Vec<Token> = tokens.collect();
+
+        if origin.is_none() {
+            if op != ".ORIG" { return Err(error("expected .ORIG before any other statement".to_owned())) }
+            let start = expect_operands(&operands, 1).and_then(|_| number(&operands[0]))
+                .and_then(|value| check_range(value, 0, 0xFFFF, ".ORIG address"))
+                .map_err(&error)?;
+
+            origin = Some(start as Address);
+            address = start as u32;
+            continue
+        }
+
+        let kind = match op.as_ref() {
+            ".ORIG" => return Err(error("only one .ORIG is allowed per file".to_owned())),
+            ".END" => break,
+            ".FILL" => {
+                expect_operands(&operands, 1).map_err(&error)?;
+                StatementKind::Fill(operands[0].clone())
+            },
+            ".BLKW" => {
+                let count = expect_operands(&operands, 1).and_then(|_| number(&operands[0]))
+                    .and_then(|value| check_range(value, 1, 0xFFFF, ".BLKW size"))
+                    .map_err(&error)?;
+                StatementKind::Data(vec![0; count as usize])
+            },
+            ".STRINGZ" => {
+                expect_operands(&operands, 1).map_err(&error)?;
+                match operands[0] {
+                    Token::Str(ref string) => {
+                        let mut words: Vec<Instruction> = string.chars().map(|c| c as Instruction).collect();
+                        words.push(0);
+                        StatementKind::Data(words)
+                    },
+                    Token::Word(_) => return Err(error(".STRINGZ expects a quoted string".to_owned()))
+                }
+            },
+            _ if op.starts_with('.') => return Err(error(format!("unknown directive {}", op))),
+            _ if is_mnemonic(&op) => StatementKind::Operation(op, operands),
+            _ => return Err(error(format!("unknown instruction {}", op)))
+        };
+
+        let size = match kind {
+            StatementKind::Data(ref words) => words.len() as u32,
+            _ => 1
+        };
+
+        statements.push(Statement { line, address: address as Address, kind });
+
+        address += size;
+        if address > 0x10000 {
+            return Err(error("program extends past xFFFF".to_owned()))
+        }
+    }
+
+    match origin {
+        Some(start) => Ok((start, statements, symbols)),
+        None => Err(LC3Error::AssemblyError(source.lines().count(), "missing .ORIG".to_owned()))
+    }
+}
+
+fn is_mnemonic(word: &str) -> bool {
+    let upper = word.to_uppercase();
+    match upper.as_ref() {
+        "ADD" | "AND" | "NOT" | "JMP" | "RET" | "JSR" | "JSRR" | "LD" | "LDI" | "LDR" | "LEA" |
+        "ST" | "STI" | "STR" | "TRAP" | "RTI" |
+        "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => true,
+        _ => branch_condition(&upper).is_some()
+    }
+}
+
+// BR, BRn, BRzp, ... => the nzp bits of the branch (plain BR is unconditional)
+fn branch_condition(upper: &str) -> Option<Instruction> {
+    if !upper.starts_with("BR") { return None }
+
+    let flags = &upper[2..];
+    if flags.is_empty() { return Some(0b111) }
+
+    let mut nzp = 0;
+    let mut rest = flags;
+    for &(flag, bit) in &[('N', 0b100), ('Z', 0b010), ('P', 0b001)] {
+        if rest.starts_with(flag) {
+            nzp |= bit;
+            rest = &rest[1..];
+        }
+    }
+
+    if rest.is_empty() { Some(nzp) } else { None }
+}
+
+fn is_valid_label(label: &str) -> bool {
+    let starts_well = label.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+    starts_well
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && register(&Token::Word(label.to_owned())).is_err()
+        && parse_number(label).is_none()
+}
+
+
+//////////////////////////////////////////////////////
+// SECOND PASS (encoding)
+//////////////////////////////////////////////////////
+
+
+fn encode(mnemonic: &str, operands: &[Token], address: Address, symbols: &SymbolTable) -> Result<Instruction, String> {
+    // PC-relative offsets are measured from the incremented PC
+    let pc = address.wrapping_add(1);
+
+    if let Some(nzp) = branch_condition(mnemonic) {
+        expect_operands(operands, 1)?;
+        return Ok(nzp << 9 | pc_offset(&operands[0], pc, 9, symbols)?)
+    }
+
+    let instr = match mnemonic {
+        "ADD" | "AND" => {
+            expect_operands(operands, 3)?;
+            let op_code = if mnemonic == "ADD" { 0x1000 } else { 0x5000 };
+            let dr = register(&operands[0])?;
+            let sr1 = register(&operands[1])?;
+
+            let last = match register(&operands[2]) {
+                Ok(sr2) => sr2,
+                Err(_) => 0x20 | immediate(&operands[2], 5)?
+            };
+
+            op_code | dr << 9 | sr1 << 6 | last
+        },
+        "NOT" => {
+            expect_operands(operands, 2)?;
+            0x903F | register(&operands[0])? << 9 | register(&operands[1])? << 6
+        },
+        "JMP" | "JSRR" => {
+            expect_operands(operands, 1)?;
+            let op_code = if mnemonic == "JMP" { 0xC000 } else { 0x4000 };
+            op_code | register(&operands[0])? << 6
+        },
+        "RET" => {
+            expect_operands(operands, 0)?;
+            0xC1C0
+        },
+        "JSR" => {
+            expect_operands(operands, 1)?;
+            0x4800 | pc_offset(&operands[0], pc, 11, symbols)?
+        },
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            expect_operands(operands, 2)?;
+            let op_code = match mnemonic {
+                "LD" => 0x2000,
+                "LDI" => 0xA000,
+                "LEA" => 0xE000,
+                "ST" => 0x3000,
+                _ => 0xB000
+            };
+            op_code | register(&operands[0])? << 9 | pc_offset(&operands[1], pc, 9, symbols)?
+        },
+        "LDR" | "STR" => {
+            expect_operands(operands, 3)?;
+            let op_code = if mnemonic == "LDR" { 0x6000 } else { 0x7000 };
+            op_code | register(&operands[0])? << 9 | register(&operands[1])? << 6 | immediate(&operands[2], 6)?
+        },
+        "TRAP" => {
+            expect_operands(operands, 1)?;
+            let trap_vector = check_range(number(&operands[0])?, 0, 0xFF, "trap vector")?;
+            0xF000 | trap_vector as Instruction
+        },
+        "RTI" => {
+            expect_operands(operands, 0)?;
+            0x8000
+        },
+        _ => {
+            expect_operands(operands, 0)?;
+            match mnemonic {
+                "GETC" => 0xF020,
+                "OUT" => 0xF021,
+                "PUTS" => 0xF022,
+                "IN" => 0xF023,
+                "PUTSP" => 0xF024,
+                "HALT" => 0xF025,
+                _ => return Err(format!("unknown instruction {}", mnemonic))
+            }
+        }
+    };
+
+    Ok(instr)
+}
+
+fn fill_value(operand: &Token, symbols: &SymbolTable) -> Result<Instruction, String> {
+    if let Token::Word(ref word) = *operand {
+        if let Some(&address) = symbols.get(word) {
+            return Ok(address)
+        }
+        if is_valid_label(word) { return Err(format!("undefined label {}", word)) }
+    }
+
+    let value = check_range(number(operand)?, -0x8000, 0xFFFF, ".FILL value")?;
+    Ok(value as Instruction)
+}
+
+fn expect_operands(operands: &[Token], count: usize) -> Result<(), String> {
+    if operands.len() == count {
+        Ok(())
+    } else {
+        Err(format!("expected {} operand(s) but found {}", count, operands.len()))
+    }
+}
+
+fn register(operand: &Token) -> Result<Instruction, String> {
+    if let Token::Word(ref word) = *operand {
+        let upper = word.to_uppercase();
+        if upper.len() == 2 && upper.starts_with('R') {
+            if let Some(num) = upper[1..].parse::<Instruction>().ok().filter(|&num| num < 8) {
+                return Ok(num)
+            }
+        }
+    }
+
+    Err(format!("{} is not a register (R0 - R7)", operand))
+}
+
+// A signed immediate value that must fit into the given number of bits.
+fn immediate(operand: &Token, num_bits: u32) -> Result<Instruction, String> {
+    let value = signed_field(number(operand)?, num_bits, "immediate value")?;
+    Ok(value)
+}
+
+// Labels are converted into an offset from the (incremented) PC; plain
+// numbers are taken to already be offsets.
+fn pc_offset(operand: &Token, pc: Address, num_bits: u32, symbols: &SymbolTable) -> Result<Instruction, String> {
+    let offset = match *operand {
+        Token::Word(ref word) if symbols.contains_key(word) => {
+            i32::from(symbols[word]) - i32::from(pc)
+        },
+        Token::Word(ref word) if is_valid_label(word) => {
+            return Err(format!("undefined label {}", word))
+        },
+        _ => number(operand)?
+    };
+
+    signed_field(offset, num_bits, &format!("offset to {}", operand))
+}
+
+fn signed_field(value: i32, num_bits: u32, what: &str) -> Result<Instruction, String> {
+    let max = (1 << (num_bits - 1)) - 1;
+    let min = -(1 << (num_bits - 1));
+    let value = check_range(value, min, max, what)?;
+
+    Ok((value as Instruction) & ((1 << num_bits) - 1))
+}
+
+fn check_range(value: i32, min: i32, max: i32, what: &str) -> Result<i32, String> {
+    if value >= min && value <= max {
+        Ok(value)
+    } else {
+        Err(format!("{} {} is out of range ({} to {})", what, value, min, max))
+    }
+}
+
+
+//////////////////////////////////////////////////////
+// TOKENIZING
+//////////////////////////////////////////////////////
+
+
+#[derive(Clone)]
+enum Token {
+    Word(String),
+    Str(String)
+}
+
+impl ::std::fmt::Display for Token {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Token::Word(ref word) => write!(f, "{}", word),
+            Token::Str(ref string) => write!(f, "{:?}", string)
+        }
+    }
+}
+
+fn number(operand: &Token) -> Result<i32, String> {
+    match *operand {
+        Token::Word(ref word) => parse_number(word).ok_or_else(|| format!("{} is not a number", word)),
+        Token::Str(ref string) => Err(format!("{:?} is not a number", string))
+    }
+}
+
+// Accepts #decimal, decimal, xHEX, 0xHEX and bBINARY literals.
+pub fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+
+    let value = if let Some(decimal) = digits.strip_prefix('#') {
+        decimal.parse::<i32>().ok()
+    } else if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = digits.strip_prefix('x').or_else(|| digits.strip_prefix('X')) {
+        i32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = digits.strip_prefix('b').or_else(|| digits.strip_prefix('B')) {
+        i32::from_str_radix(binary, 2).ok()
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<i32>().ok()
+    } else {
+        None
+    };
+
+    value.map(|value| if negative { -value } else { value })
+}
+
+// Splits a line into words and quoted strings, dropping commas and comments.
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c == ';' {
+            break
+        } else if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some('0') => '\0',
+                            Some('\\') => '\\',
+                            Some('"') => '"',
+                            Some(other) => return Err(format!("unknown escape sequence \\{}", other)),
+                            None => return Err("unterminated string".to_owned())
+                        };
+                        string.push(escaped);
+                    },
+                    Some(other) => string.push(other),
+                    None => return Err("unterminated string".to_owned())
+                }
+            }
+            tokens.push(Token::Str(string));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == ';' || c == '"' { break }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
@@ -0,0 +1,129 @@
+use console::FileConsole;
+use cpu::CPU;
+use debugger::Debugger;
+use errors::{Failable, LC3Error};
+use loader::load_program;
+use trace::TraceWriter;
+
+//////////////////////////////////////////////////////
+// BATCH RUNS
+//////////////////////////////////////////////////////
+
+// Process exit codes for batch runs.  Anything that goes wrong while loading
+// (a missing file, a bad argument, a program that won't assemble) is a usage
+// error; anything that goes wrong once the program is running, including
+// failing to write its output or trace, is a fault.
+pub const EXIT_HALTED: i32 = 0;
+pub const EXIT_BAD_USAGE: i32 = 1;
+pub const EXIT_STEP_LIMIT_EXCEEDED: i32 = 2;
+pub const EXIT_FAULTED: i32 = 3;
+
+// run [program...] [--input file] [--max-steps n] [--os image] [--native-traps] [--trace file]
+// -- runs the programs until HALT without the console; execution starts at the
+// first one.  Program output goes to stdout and anything from the simulator to
+// stderr.  With --native-traps the machine has no operating system, so its
+// banners (like the one HALT prints) stay out of the program's output.
+pub fn batch_command(args: &[String]) -> i32 {
+    let mut debugger = Debugger::new(machine(args));
+    debugger.set_history_size(0);
+
+    let max_steps = match load_batch(&mut debugger, args) {
+        Ok(max_steps) => max_steps,
+        Err(error) => {
+            eprintln!("Failed to load program: {reason}.", reason=error);
+            return EXIT_BAD_USAGE
+        }
+    };
+
+    let result = run_batch(&mut debugger, max_steps);
+    if let Err(error) = debugger.set_trace(None) {
+        eprintln!("Failed to write trace: {reason}.", reason=error);
+        return EXIT_FAULTED
+    }
+
+    let cpu = &debugger.cpu;
+    match result {
+        // the operating system's exception handlers halt the machine too
+        Ok(true) => match cpu.last_exception {
+            Some(vector) => {
+                eprintln!("Program faulted; it raised exception x{:02X}.", vector);
+                EXIT_FAULTED
+            },
+            None => EXIT_HALTED
+        },
+        Ok(false) => {
+            eprintln!("Step limit exceeded; the program did not halt within {} instructions.", max_steps.unwrap_or(0));
+            EXIT_STEP_LIMIT_EXCEEDED
+        },
+        Err(error) => {
+            eprintln!("Program faulted at x{:04X}: {reason}.", cpu.pc, reason=error);
+            EXIT_FAULTED
+        }
+    }
+}
+
+// The machine has to be chosen before anything (like the input) is attached to it.
+fn machine(args: &[String]) -> CPU {
+    if args.iter().any(|arg| arg == "--native-traps") {
+        CPU::without_operating_system()
+    } else {
+        CPU::new()
+    }
+}
+
+// Loads everything named on the command line, returning the step limit.
+fn load_batch(debugger: &mut Debugger, args: &[String]) -> Failable<Option<u64>> {
+    let mut filenames = Vec::new();
+    let mut max_steps = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--input" => {
+                let input = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                debugger.cpu.console = Box::new(FileConsole::open(input)?);
+            },
+            "--max-steps" => {
+                let steps = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                max_steps = Some(steps.parse().or(Err(LC3Error::BadArguement))?);
+            },
+            "--os" => {
+                let image = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                debugger.cpu.load_operating_system(&load_program(image)?);
+            },
+            "--trace" => {
+                let trace = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                debugger.set_trace(Some(TraceWriter::create(trace)?))?;
+            },
+            "--native-traps" => (), // already chosen by machine
+            _ => filenames.push(arg)
+        }
+    }
+
+    if filenames.is_empty() { return Err(LC3Error::MissingProgramFile) }
+
+    let cpu = &mut debugger.cpu;
+    let mut start = None;
+    for filename in filenames {
+        let program = load_program(filename)?;
+        cpu.load_program(&program);
+        start = start.or(Some(program.program_counter_start()));
+    }
+    cpu.pc = start.unwrap_or(cpu.pc);
+
+    Ok(max_steps)
+}
+
+// Returns whether the CPU halted (rather than running out of steps).
+fn run_batch(debugger: &mut Debugger, max_steps: Option<u64>) -> Failable<bool> {
+    let mut steps = 0;
+    while debugger.cpu.running {
+        if max_steps == Some(steps) { return Ok(false) }
+
+        debugger.step()?;
+        steps += 1;
+    }
+
+    Ok(true)
+}
+
@@ -0,0 +1,112 @@
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use assembler;
+use cpu::CPU;
+use debug_map::DebugMap;
+use debugger::Debugger;
+use disassembler;
+use errors::{Failable, LC3Error};
+use gdb::GdbStub;
+use loader::{load_program, load_program_file};
+
+//////////////////////////////////////////////////////
+// SUBCOMMANDS
+//////////////////////////////////////////////////////
+
+// The command line's asm, disasm, and gdb subcommands.  Each takes the
+// arguments after the subcommand's name.
+
+// asm [source.asm] [output.hex|output.obj] -- the output defaults to the
+// source name with a .hex extension.  A debug map for it is written beside
+// it with a .dbg extension, where the loader will find it.
+pub fn assemble_command(args: &[String]) -> Failable<()> {
+    let source = args.first().ok_or(LC3Error::MissingProgramFile)?;
+    let output = args.get(1).cloned()
+        .unwrap_or_else(|| Path::new(source).with_extension("hex").to_string_lossy().into_owned());
+    let debug_map_file = Path::new(&output).with_extension("dbg");
+
+    let assembly = assembler::assemble_file(source)?;
+    assembly.program.write_file(&output)?;
+    DebugMap::from_source_map(source, &assembly.source_map).write_file(&debug_map_file)?;
+
+    println!("Assembled {} into {} (debug map in {})", source, output, debug_map_file.display());
+    Ok(())
+}
+
+// disasm [program] -- prints every word of the program as LC3 assembly, with labels when they're known
+pub fn disassemble_command(args: &[String]) -> Failable<()> {
+    let filename = args.first().ok_or(LC3Error::MissingProgramFile)?;
+    let loaded = load_program_file(filename)?;
+
+    for line in disassembler::disassemble_program(&loaded.program, &loaded.symbols) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+// The port GDB users conventionally point "target remote" at.
+const DEFAULT_GDB_PORT: u16 = 1234;
+
+// gdb [program] [--port n] [--socket path] [--os image] -- waits for a GDB
+// remote protocol connection on localhost (or a Unix socket) and serves it
+// until the debugger detaches.
+pub fn gdb_command(args: &[String]) -> Failable<()> {
+    let mut debugger = Debugger::new(CPU::new());
+    let mut filename = None;
+    let mut port = DEFAULT_GDB_PORT;
+    let mut socket = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--port" => {
+                let num = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                port = num.parse().or(Err(LC3Error::BadArguement))?;
+            },
+            "--socket" => socket = Some(args.next().ok_or(LC3Error::CommandMissingArguement)?),
+            "--os" => {
+                let image = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                debugger.cpu.load_operating_system(&load_program(image)?);
+            },
+            _ => filename = Some(arg)
+        }
+    }
+
+    let filename = filename.ok_or(LC3Error::MissingProgramFile)?;
+    debugger.cpu.load_program(&load_program(filename)?);
+
+    match socket {
+        Some(path) => serve_unix_socket(&mut debugger, path),
+        None => {
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            println!("Waiting for GDB on {}", listener.local_addr()?);
+            let (stream, peer) = listener.accept()?;
+            println!("GDB connected from {}", peer);
+
+            // packets are small and answered one at a time, so don't let them sit in a buffer
+            stream.set_nodelay(true)?;
+            GdbStub::new(&mut debugger, stream).serve()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve_unix_socket(debugger: &mut Debugger, path: &str) -> Failable<()> {
+    let listener = UnixListener::bind(path)?;
+    println!("Waiting for GDB on {}", path);
+    let (stream, _) = listener.accept()?;
+    println!("GDB connected");
+
+    let result = GdbStub::new(debugger, stream).serve();
+    std::fs::remove_file(path)?;
+    result
+}
+
+#[cfg(not(unix))]
+fn serve_unix_socket(_debugger: &mut Debugger, _path: &str) -> Failable<()> {
+    Err(LC3Error::BadArguement)
+}
+
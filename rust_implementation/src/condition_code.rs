@@ -2,6 +2,7 @@ use std::fmt;
 use cpu::Word;
 
 // Negative, Zero, and Positive
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ConditionCode { N, Z, P }
 
 impl ConditionCode {
@@ -13,6 +14,15 @@ impl ConditionCode {
         }
     }
 
+    // The inverse of bit(); anything other than a single n or p bit is treated as Z.
+    pub fn from_bit(bit: i32) -> ConditionCode {
+        match bit {
+            4 => ConditionCode::N,
+            1 => ConditionCode::P,
+            _ => ConditionCode::Z,
+        }
+    }
+
     pub fn from_value(val: Word) -> ConditionCode {
         if val < 0 {
             ConditionCode::N
@@ -0,0 +1,206 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Bytes, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+//////////////////////////////////////////////////////
+// CONSOLE I/O
+//////////////////////////////////////////////////////
+
+// Where the machine's character input comes from and its output goes to.  The
+// CPU owns one, and both the native traps and the keyboard/display devices go
+// through it, so the simulator can be driven by a person, a script, or a test.
+pub trait Console {
+    // Waits for the next input character; None once the input has run out.
+    fn read_byte(&mut self) -> Option<u8>;
+
+    // The next input character if one is available without waiting.
+    fn poll_byte(&mut self) -> Option<u8> {
+        None
+    }
+
+    // Whether the input has run out for good, so that no character is ever
+    // coming however long a program polls for one.
+    fn input_closed(&self) -> bool {
+        false
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+}
+
+
+// The terminal the simulator was started from.
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = stdin_bytes().recv().ok();
+        if byte.is_none() { STDIN_CLOSED.store(true, Ordering::SeqCst) }
+        byte
+    }
+
+    // keys typed while the program runs, for the keyboard's ready bit and interrupt
+    fn poll_byte(&mut self) -> Option<u8> {
+        match stdin_bytes().try_recv() {
+            Ok(byte) => Some(byte),
+            Err(TryRecvError::Disconnected) => {
+                STDIN_CLOSED.store(true, Ordering::SeqCst);
+                None
+            },
+            Err(TryRecvError::Empty) => None
+        }
+    }
+
+    fn input_closed(&self) -> bool {
+        STDIN_CLOSED.load(Ordering::SeqCst)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(bytes)?;
+        stdout.flush()
+    }
+}
+
+// Set once stdin has ended and every byte of it has been read.
+static STDIN_CLOSED: AtomicBool = AtomicBool::new(false);
+
+// Stdin is read on a thread of its own, so whether a key has been typed can be
+// checked without waiting for one.  The REPL's commands are read through it as
+// well, so commands and program input still arrive in the order they were typed.
+fn stdin_bytes() -> MutexGuard<'static, Receiver<u8>> {
+    static STDIN: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+
+    let receiver = STDIN.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) => if sender.send(byte).is_err() { break },
+                    Err(_) => break
+                }
+            }
+        });
+        Mutex::new(receiver)
+    });
+    receiver.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// One line from stdin without its newline; None once stdin has run out.
+pub fn read_stdin_line() -> Option<String> {
+    let stdin = stdin_bytes();
+    let mut line = Vec::new();
+    loop {
+        match stdin.recv() {
+            Ok(b'\n') => break,
+            Ok(byte) => line.push(byte),
+            // a last line without a newline still counts
+            Err(_) if line.is_empty() => return None,
+            Err(_) => break
+        }
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+
+// Input and output kept in memory.  Clones share the same buffers, so a test
+// can hand one to the CPU and keep another to feed input and check output.
+#[derive(Clone, Default)]
+pub struct BufferConsole {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>
+}
+
+impl BufferConsole {
+    pub fn new(input: &[u8]) -> BufferConsole {
+        let console = BufferConsole::default();
+        console.push_input(input);
+        console
+    }
+
+    pub fn push_input(&self, input: &[u8]) {
+        self.input.borrow_mut().extend(input);
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        self.output.borrow().clone()
+    }
+
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output.borrow()).into_owned()
+    }
+
+    pub fn clear_output(&self) {
+        self.output.borrow_mut().clear();
+    }
+}
+
+impl Console for BufferConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        self.read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+
+// Reads input from a file and writes output either to stdout or to another file.
+pub struct FileConsole {
+    input: Bytes<BufReader<File>>,
+    input_closed: bool,
+    output: Box<dyn Write>
+}
+
+impl FileConsole {
+    pub fn open<P: AsRef<Path>>(input: P) -> io::Result<FileConsole> {
+        Ok(FileConsole {
+            input: BufReader::new(File::open(input)?).bytes(),
+            input_closed: false,
+            output: Box::new(io::stdout())
+        })
+    }
+
+    pub fn create<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<FileConsole> {
+        Ok(FileConsole {
+            input: BufReader::new(File::open(input)?).bytes(),
+            input_closed: false,
+            output: Box::new(File::create(output)?)
+        })
+    }
+}
+
+impl Console for FileConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.input.next().and_then(Result::ok);
+        self.input_closed = byte.is_none();
+        byte
+    }
+
+    // the whole file is already there, so input never has to be waited for
+    fn poll_byte(&mut self) -> Option<u8> {
+        self.read_byte()
+    }
+
+    fn input_closed(&self) -> bool {
+        self.input_closed
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)?;
+        self.output.flush()
+    }
+}
@@ -2,66 +2,156 @@ use errors::LC3Error;
 use program::Program;
 use errors::Failable;
 use condition_code::ConditionCode;
+use privilege::Privilege;
+use interrupt::{InterruptController, InterruptRequest};
+use console::{Console, StdConsole};
 use utils::{UnsignedBitSelection};
 use nice_vector::Vector;
+use memory::MemoryBus;
+use devices::{CLOCK_ENABLE_BIT, MCR};
 use operation::Operation;
+use disassembler::disassemble_with_symbols;
+use symbols::Symbols;
+use os;
 
-pub type Word = i32; // actually i16
-pub type Address = i32; // actually u16
-pub type Instruction = i32; // actually u16
+pub type Word = i16;
+pub type Address = u16;
+pub type Instruction = u16;
+
+// Native traps are handled directly by Rust code; OperatingSystem traps go
+// through the trap vector table to routines in a loaded OS image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrapMode { Native, OperatingSystem }
 
 pub const NUM_REGISTERS: i32 = 8;
 pub const NUM_MEMORY_ADDRESSES: i32 = 65536;
 
+// Service routine addresses for interrupts and exceptions live at x0100 - x01FF.
+pub const INTERRUPT_VECTOR_TABLE: Address = 0x0100;
+pub const PRIVILEGE_MODE_VIOLATION: u8 = 0x00;
+pub const ILLEGAL_OPCODE: u8 = 0x01;
+
+// The supervisor stack grows down from just below user space.
+pub const SUPERVISOR_STACK_START: Word = 0x3000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessKind { Read, Write }
+
+// A data memory access made by an instruction (instruction fetches aren't included).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: Address,
+    pub value: Word,
+    pub previous: Word // What the address held before the access (same as value for reads)
+}
+
 pub struct CPU {
-    pub mem: Vector<Address>, // Memory
+    pub mem: MemoryBus, // Memory (RAM plus device registers)
     pub reg: Vector<Word>, // Registers
     pub running: bool, // Are instructions being executed?
 
     pub pc: Address, // Program Counter
     pub ir: Instruction, // Instruction Register
-    pub cc: ConditionCode // Condition Code (used for conditional branching)
+    pub cc: ConditionCode, // Condition Code (used for conditional branching)
+
+    // Processor Status Register (along with cc)
+    pub privilege: Privilege,
+    pub priority: u8,
+
+    pub saved_ssp: Word, // Saved Supervisor Stack Pointer (R6 while in user mode)
+    pub saved_usp: Word, // Saved User Stack Pointer (R6 while in supervisor mode)
+
+    pub interrupts: InterruptController,
+    pub interrupt_taken: Option<InterruptRequest>, // Set when the last cycle entered an interrupt instead of executing
+    pub trap_mode: TrapMode,
+    pub last_exception: Option<u8>, // Vector of the most recent exception raised
+    pub console: Box<dyn Console>, // Character input and output for traps and devices
+
+    pub accesses: Vec<MemoryAccess> // Memory accesses made by the last cycle
 }
 
 impl CPU {
+    // A machine booted with the bundled operating system (see os.rs).
     pub fn new() -> CPU {
+        let mut cpu = CPU::without_operating_system();
+        cpu.load_operating_system(&os::image());
+        cpu
+    }
+
+    // A machine with empty low memory whose TRAPs are handled natively in Rust.
+    pub fn without_operating_system() -> CPU {
         CPU {
-            mem: Vector::new(NUM_MEMORY_ADDRESSES),
+            mem: MemoryBus::with_standard_devices(),
             reg: Vector::new(NUM_REGISTERS),
             running: true,
             pc: 0,
             ir: 0,
-            cc: ConditionCode::Z
+            cc: ConditionCode::Z,
+            privilege: Privilege::User,
+            priority: 0,
+            saved_ssp: SUPERVISOR_STACK_START,
+            saved_usp: 0,
+            interrupts: InterruptController::new(),
+            interrupt_taken: None,
+            trap_mode: TrapMode::Native,
+            last_exception: None,
+            console: Box::new(StdConsole),
+            accesses: Vec::new()
         }
     }
 
-    pub fn load_program(&mut self, program: &Program) -> () {
+    pub fn load_program(&mut self, program: &Program) {
         self.pc = program.program_counter_start();
 
         // load program instructions into memory
-        let mut temp_pc = self.pc as u16;
+        let mut temp_pc = self.pc;
         for instr in program.instructions() {
-            self.mem[temp_pc] = *instr;
+            self.mem[temp_pc] = *instr as Word;
             temp_pc = temp_pc.wrapping_add(1);
         }
     }
 
+    // Loads an operating system image (trap vector table, interrupt vector
+    // table, and service routines) and sends TRAPs through it from now on.
+    // The PC is left alone so a user program can be loaded afterwards.
+    pub fn load_operating_system(&mut self, image: &Program) {
+        let pc = self.pc;
+        self.load_program(image);
+        self.pc = pc;
+        self.trap_mode = TrapMode::OperatingSystem;
+    }
+
     pub fn run_one_instruction_cycle(&mut self) -> Failable<()> {
         if !self.running { return Err(LC3Error::CpuNotRunning) }
+        self.accesses.clear();
+        self.interrupt_taken = None;
 
-        // load current instruction into instruction register
-        self.ir = self.mem[self.pc];
-
-        self.pc += 1;
-        if self.pc > 0xffff {
-            self.pc = 0x0000
+        // interrupts are only taken between instructions, and taking one is a
+        // cycle of its own so that the service routine's first instruction is
+        // fetched (and can be stopped at) like any other
+        self.mem.tick(&mut self.interrupts, &mut *self.console);
+        if let Some(request) = self.interrupts.highest_above(self.priority) {
+            self.enter_service_routine(request.vector, Some(request.priority))?;
+            self.interrupts.acknowledge(request);
+            self.interrupt_taken = Some(request);
+            return Ok(())
         }
 
+        // load current instruction into instruction register
+        self.ir = self.mem[self.pc] as Instruction;
+        self.pc = self.pc.wrapping_add(1);
+
         // execute current instruction
         let op_code = self.ir.bits(15, 12) as u8;
         let operation = Operation::from_code(op_code)?;
         self.run_operation(operation)?;
 
+        // clearing the clock enable bit of the MCR stops the machine
+        if self.mem.handles(MCR) && self.mem.peek(MCR) & CLOCK_ENABLE_BIT == 0 {
+            self.running = false;
+        }
+
         Ok(())
     }
 
@@ -70,6 +160,101 @@ impl CPU {
         Ok(())
     }
 
+    // Memory reads and writes made on behalf of an instruction go through these
+    // so that debuggers can see what the instruction touched.
+    pub fn read_memory(&mut self, addr: Address) -> Failable<Word> {
+        let value = self.mem.read(addr, &mut *self.console)?;
+        self.accesses.push(MemoryAccess { kind: AccessKind::Read, address: addr, value, previous: value });
+        Ok(value)
+    }
+
+    pub fn write_memory(&mut self, addr: Address, value: Word) {
+        let previous = self.mem.peek(addr);
+        self.mem.write(addr, value, &mut *self.console);
+        self.accesses.push(MemoryAccess { kind: AccessKind::Write, address: addr, value, previous });
+    }
+
+    // PSR[15] = privilege, PSR[10:8] = priority, PSR[2:0] = condition code
+    pub fn psr(&self) -> Word {
+        let psr = self.privilege.bit() << 15 | i32::from(self.priority & 0b111) << 8 | self.cc.bit();
+        psr as Word
+    }
+
+    pub fn set_psr(&mut self, psr: Word) {
+        let psr = psr as Instruction;
+        self.privilege = Privilege::from_bit(psr.bits(15, 15));
+        self.priority = psr.bits(10, 8) as u8;
+        self.cc = ConditionCode::from_bit(psr.bits(2, 0));
+    }
+
+    // R6 is the stack pointer of whichever stack the current mode is using.
+    pub fn push_stack(&mut self, value: Word) {
+        let sp = self.reg[6].wrapping_sub(1);
+        self.reg[6] = sp;
+        self.write_memory(sp as Address, value);
+    }
+
+    pub fn pop_stack(&mut self) -> Failable<Word> {
+        let sp = self.reg[6];
+        self.reg[6] = sp.wrapping_add(1);
+        self.read_memory(sp as Address)
+    }
+
+    // Switches to the supervisor stack, saves the PSR and PC on it, and jumps
+    // to the service routine for the vector.  Interrupts also raise the
+    // priority level; exceptions leave it alone.
+    pub fn enter_service_routine(&mut self, vector: u8, priority: Option<u8>) -> Failable<()> {
+        let return_pc = self.pc;
+        self.start_service_routine(vector, priority, return_pc)
+    }
+
+    // Exceptions save the address of the faulting instruction rather than the next one.
+    pub fn raise_exception(&mut self, vector: u8) -> Failable<()> {
+        let faulting_pc = self.pc.wrapping_sub(1);
+        self.last_exception = Some(vector);
+        self.start_service_routine(vector, None, faulting_pc)
+    }
+
+    fn start_service_routine(&mut self, vector: u8, priority: Option<u8>, return_pc: Address) -> Failable<()> {
+        let routine = self.mem[INTERRUPT_VECTOR_TABLE + Address::from(vector)] as Address;
+        if routine == 0 { return Err(LC3Error::MissingServiceRoutine(vector)) }
+
+        self.save_context(return_pc);
+
+        if let Some(priority) = priority { self.priority = priority }
+        self.pc = routine;
+        Ok(())
+    }
+
+    // TRAP through the trap vector table (x0000 - x00FF) into an operating
+    // system's service routine: R7 <- PC, then the same supervisor switch as
+    // an interrupt so the routine can return with RTI.
+    pub fn enter_trap_routine(&mut self, trap_vector: u8) -> Failable<()> {
+        let routine = self.read_memory(Address::from(trap_vector))? as Address;
+        if routine == 0 { return Err(LC3Error::UnsupportedTrapCode(i32::from(trap_vector))) }
+
+        self.reg[7] = self.pc as Word;
+        let return_pc = self.pc;
+        self.save_context(return_pc);
+
+        self.pc = routine;
+        Ok(())
+    }
+
+    // Switches to supervisor mode (and its stack) and saves the PSR and the
+    // PC to return to on the supervisor stack.
+    fn save_context(&mut self, return_pc: Address) {
+        let psr = self.psr();
+        if self.privilege == Privilege::User {
+            self.saved_usp = self.reg[6];
+            self.reg[6] = self.saved_ssp;
+            self.privilege = Privilege::Supervisor;
+        }
+
+        self.push_stack(psr);
+        self.push_stack(return_pc as Word);
+    }
+
     // updates condition code based on value being assigned to the given
     // destination register
     pub fn set_dr(&mut self, reg_num: i32, val: Word) {
@@ -85,13 +270,20 @@ impl CPU {
         println!("Control Unit:");
         println!("PC = {:04X}    IR = {:04X}    CC = {}    RUNNING: {}",
                  self.pc, self.ir, self.cc, self.running);
+        println!("PSR = {:04X}    MODE = {}    PRIORITY = {}    SAVED SSP = {:04X}    SAVED USP = {:04X}",
+                 self.psr(), self.privilege, self.priority, self.saved_ssp, self.saved_usp);
     }
 
-    // Prints all (useful) instructions currently in memory in both hex and decimal.
-    pub fn print_memory(&self) {
+    // Prints all (useful) instructions currently in memory in hex, decimal, and
+    // as assembly, with the label (if any) of each address and of the
+    // addresses the instructions refer to.
+    pub fn print_memory(&self, symbols: &Symbols) {
         println!("Memory (addresses x0000 - xFFFF, excluding NOP instructions)");
-        for (i, instr) in self.mem.vals.iter().enumerate().filter(|(_i, &x)| x != 0) {
-            println!("{:04X}: {:04X}    {}", i, instr, instr)
+        for (i, instr) in self.mem.ram.vals.iter().enumerate().filter(|(_i, &x)| x != 0) {
+            let address = i as Address;
+            let label = symbols.describe(address).map(|label| format!(" ({})", label)).unwrap_or_default();
+            println!("{:04X}{}: {:04X}    {:<8}{}", i, label, instr, instr,
+                     disassemble_with_symbols(*instr as Instruction, address, symbols))
         }
     }
 
@@ -103,9 +295,9 @@ impl CPU {
         }
     }
 
-    pub fn print_all_info(&self) {
+    pub fn print_all_info(&self, symbols: &Symbols) {
         self.print_control_unit();
-        self.print_memory();
+        self.print_memory(symbols);
     }
 
 
@@ -117,10 +309,16 @@ impl CPU {
     pub fn set_pc_address(&mut self, addr: Address) {
         self.pc = addr;
         self.running = true;
+
+        if self.mem.handles(MCR) {
+            let mcr = self.mem.peek(MCR);
+            self.mem.write(MCR, mcr | CLOCK_ENABLE_BIT, &mut *self.console);
+        }
     }
 
+    // Goes through the memory bus, so device registers can be set as well.
     pub fn set_memory_address_value(&mut self, addr: Address, val: Word) {
-        self.mem[addr] = val;
+        self.mem.write(addr, val, &mut *self.console);
     }
 
     pub fn set_register_value(&mut self, reg_num: i32, val: Word) {
@@ -141,4 +339,10 @@ impl CPU {
 
         Ok(())
     }
+}
+
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new()
+    }
 }
\ No newline at end of file
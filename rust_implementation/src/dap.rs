@@ -0,0 +1,508 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use serde_json::Value;
+use console::BufferConsole;
+use cpu::{Address, Instruction, Word, CPU};
+use debugger::{Debugger, StopReason};
+use devices::{DDR, DSR, KBDR, KBSR, MCR};
+use disassembler::disassemble;
+use errors::{Failable, LC3Error};
+use loader::load_program_file;
+
+//////////////////////////////////////////////////////
+// DEBUG ADAPTER PROTOCOL
+//////////////////////////////////////////////////////
+
+// A server for the Debug Adapter Protocol, the one editors such as VS Code
+// use to talk to debuggers.  It launches a program in a Debugger of its own
+// and lets the editor set breakpoints on source lines, step (over, into, out
+// of, and back through) instructions, and look at registers and memory.
+//
+// Messages are JSON, each preceded by a Content-Length header.  Stdout
+// carries the protocol, so whatever the program prints is sent to the editor
+// as output events instead.
+
+// The LC3 has only the one thread.
+const THREAD_ID: u64 = 1;
+
+// How many instructions run between checks for a pause from the editor.
+const INSTRUCTIONS_PER_POLL: u32 = 4096;
+
+// variablesReference values for the scopes and everything nested in them.
+const REGISTERS: u64 = 1;
+const MEMORY: u64 = 2;
+const PSR_FIELDS: u64 = 3;
+const NEAR_PC: u64 = 4;
+const STACK: u64 = 5;
+const DEVICE_REGISTERS: u64 = 6;
+
+// How much of memory the regions around the PC and the top of the stack show.
+const WORDS_BEFORE_PC: Address = 4;
+const WORDS_AFTER_PC: Address = 8;
+const STACK_WORDS: Address = 8;
+
+const DEVICES: [(&str, Address); 5] = [("KBSR", KBSR), ("KBDR", KBDR), ("DSR", DSR), ("DDR", DDR), ("MCR", MCR)];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Motion { Continue, StepIn, StepOver, StepOut }
+
+// A launched program.  What is known about its source is in the debugger's debug map.
+struct Session {
+    debugger: Debugger,
+    console: BufferConsole,
+    breakpoints: BTreeMap<String, Vec<Address>>, // The ones set from each source, replaced all at once
+    stop_on_entry: bool
+}
+
+pub struct DapServer<W: Write> {
+    requests: Receiver<Value>,
+    queued: VecDeque<Value>, // Requests that arrived while the program was running
+    output: W,
+    sequence: u64,
+    session: Option<Session>
+}
+
+impl<W: Write> DapServer<W> {
+    // Messages are read on a thread of their own so that a pause can be
+    // noticed while the program is running.
+    pub fn new<R: BufRead + Send + 'static>(input: R, output: W) -> DapServer<W> {
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || read_messages(input, &sender));
+
+        DapServer { requests, queued: VecDeque::new(), output, sequence: 0, session: None }
+    }
+
+    // Answers requests until the editor disconnects or the input ends.
+    pub fn serve(&mut self) -> Failable<()> {
+        while let Some(request) = self.next_request() {
+            if request["type"] != "request" { continue }
+            if !self.handle(&request)? { break }
+        }
+
+        Ok(())
+    }
+
+    fn next_request(&mut self) -> Option<Value> {
+        self.queued.pop_front().or_else(|| self.requests.recv().ok())
+    }
+
+    //////// REQUESTS ////////
+
+    // Returns false once the editor has disconnected.  Anything a request
+    // starts (like running the program) happens after its response is sent,
+    // since the protocol wants the response before the events that follow.
+    fn handle(&mut self, request: &Value) -> Failable<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+
+        let body = match command {
+            "initialize" => Ok(json!({ "supportsConfigurationDoneRequest": true, "supportsStepBack": true })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" | "disconnect" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "LC-3" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Memory", "variablesReference": MEMORY, "expensive": false }
+            ] })),
+            "variables" => self.variables(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => self.session().map(|_| json!({ "allThreadsContinued": true })),
+            "configurationDone" | "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" | "pause" => {
+                self.session().map(|_| Value::Null)
+            },
+            _ => Err(LC3Error::UnrecognizedConsoleCommand(command.to_owned()))
+        };
+
+        let succeeded = body.is_ok();
+        self.respond(request, body)?;
+        if !succeeded { return Ok(true) }
+
+        match command {
+            "launch" => self.send_event("initialized", Value::Null)?,
+            "configurationDone" => {
+                if self.session()?.stop_on_entry {
+                    self.stopped("entry", None)?
+                } else {
+                    self.run(Motion::Continue)?
+                }
+            },
+            "continue" => self.run(Motion::Continue)?,
+            "next" => self.run(Motion::StepOver)?,
+            "stepIn" => self.run(Motion::StepIn)?,
+            "stepOut" => self.run(Motion::StepOut)?,
+            "stepBack" => {
+                match self.session()?.debugger.step_back(1) {
+                    1 => self.stopped("step", None)?,
+                    _ => self.report(&StopReason::StartOfHistory)?
+                }
+            },
+            "reverseContinue" => {
+                let reason = self.session()?.debugger.reverse_continue();
+                self.report(&reason)?
+            },
+            // a pause while the program is running is answered by pause_requested,
+            // so the program is already stopped and there's nothing to report
+            "disconnect" => return Ok(false),
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    // launch {program, stopOnEntry, input, os}: input is what the keyboard
+    // will be typed to start with (more can be typed with evaluate), and os an
+    // operating system image to load over the bundled one.  Assembly programs are assembled with a debug map, and hex
+    // and obj programs use the one beside them; without one, a program can
+    // still be debugged, just not from its source.
+    fn launch(&mut self, args: &Value) -> Failable<Value> {
+        let filename = args["program"].as_str().ok_or(LC3Error::MissingProgramFile)?;
+
+        let console = BufferConsole::new(args["input"].as_str().unwrap_or_default().as_bytes());
+        let mut cpu = CPU::new();
+        cpu.console = Box::new(console.clone());
+
+        if let Some(image) = args["os"].as_str() {
+            cpu.load_operating_system(&load_program_file(image)?.program);
+        }
+
+        let loaded = load_program_file(filename)?;
+        cpu.load_program(&loaded.program);
+
+        let mut debugger = Debugger::new(cpu);
+        debugger.add_symbols(&loaded.symbols);
+        debugger.add_debug_map(&loaded.debug_map);
+
+        self.session = Some(Session {
+            debugger,
+            console,
+            breakpoints: BTreeMap::new(),
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false)
+        });
+
+        Ok(Value::Null)
+    }
+
+    // setBreakpoints {source, breakpoints}: replaces every breakpoint in the
+    // source.  Each goes on the first line at or after its own with any code;
+    // ones in files the debug map doesn't know can't be hit, so they're
+    // reported as unverified.
+    fn set_breakpoints(&mut self, args: &Value) -> Failable<Value> {
+        let session = self.session()?;
+        let path = args["source"]["path"].as_str().unwrap_or_default().to_owned();
+
+        for addr in session.breakpoints.remove(&path).unwrap_or_default() {
+            session.debugger.remove_breakpoint(addr);
+        }
+
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = Vec::new();
+        let mut addresses = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+
+            match session.debugger.debug_map().resolve(&path, line).filter(|_| !path.is_empty()) {
+                Some((line, addr)) => {
+                    session.debugger.add_breakpoint(addr);
+                    addresses.push(addr);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                },
+                None => breakpoints.push(json!({ "verified": false, "line": line, "message": "No code at or after this line" }))
+            }
+        }
+        session.breakpoints.insert(path, addresses);
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    // There's a single frame, for the instruction the PC is at.  Its line is
+    // the one the instruction was assembled from, if it was.
+    fn stack_trace(&mut self) -> Failable<Value> {
+        let session = self.session()?;
+        let pc = session.debugger.cpu.pc;
+        let instr = session.debugger.cpu.mem.peek(pc) as Instruction;
+
+        let mut frame = json!({
+            "id": 0,
+            "name": format!("x{:04X}: {}", pc, disassemble(instr, pc)),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("x{:04X}", pc)
+        });
+
+        if let Some(location) = session.debugger.debug_map().location(pc) {
+            let name = location.file.file_name().map(|name| name.to_string_lossy().into_owned());
+            frame["source"] = json!({ "name": name, "path": location.file });
+            frame["line"] = json!(location.line);
+            frame["column"] = json!(1);
+        }
+
+        Ok(json!({ "stackFrames": [frame], "totalFrames": 1 }))
+    }
+
+    // evaluate {expression, context}: a line typed into the editor's debug
+    // console is typed on the keyboard, newline and all, even while the
+    // program is running.  Watches and hovers have nothing to evaluate.
+    fn evaluate(&mut self, args: &Value) -> Failable<Value> {
+        if args["context"].as_str().is_some_and(|context| context != "repl") { return Err(LC3Error::BadArguement) }
+
+        let line = format!("{}\n", args["expression"].as_str().unwrap_or_default());
+        self.session()?.console.push_input(line.as_bytes());
+        Ok(json!({ "result": "", "variablesReference": 0 }))
+    }
+
+    // Memory is read without side effects, so looking at the keyboard
+    // registers doesn't consume a key.
+    fn variables(&mut self, args: &Value) -> Failable<Value> {
+        let cpu = &self.session()?.debugger.cpu;
+
+        let variables = match args["variablesReference"].as_u64().unwrap_or(0) {
+            REGISTERS => {
+                let mut registers: Vec<Value> = (0..8).map(|reg| variable(&format!("R{}", reg), word(cpu.reg[reg]))).collect();
+                registers.push(variable("PC", format!("x{:04X}", cpu.pc)));
+                registers.push(variable("IR", format!("x{:04X}", cpu.ir)));
+                registers.push(variable("CC", format!("{:?}", cpu.cc)));
+                registers.push(json!({ "name": "PSR", "value": format!("x{:04X}", cpu.psr() as u16), "variablesReference": PSR_FIELDS }));
+                registers
+            },
+            PSR_FIELDS => vec![
+                variable("Privilege", cpu.privilege.to_string()),
+                variable("Priority", cpu.priority.to_string()),
+                variable("CC", format!("{:?}", cpu.cc))
+            ],
+            MEMORY => {
+                let near_pc = cpu.pc.wrapping_sub(WORDS_BEFORE_PC);
+                let stack = cpu.reg[6] as Address;
+                vec![
+                    region("Near PC", near_pc, WORDS_BEFORE_PC + WORDS_AFTER_PC, NEAR_PC),
+                    region("Stack (R6)", stack, STACK_WORDS, STACK),
+                    json!({ "name": "Device registers", "value": "", "variablesReference": DEVICE_REGISTERS })
+                ]
+            },
+            NEAR_PC => {
+                let start = cpu.pc.wrapping_sub(WORDS_BEFORE_PC);
+                (0..WORDS_BEFORE_PC + WORDS_AFTER_PC).map(|offset| {
+                    let addr = start.wrapping_add(offset);
+                    let value = cpu.mem.peek(addr);
+                    let marker = if addr == cpu.pc { "  <- PC" } else { "" };
+                    variable(&format!("x{:04X}", addr),
+                             format!("x{:04X}  {}{}", value as u16, disassemble(value as Instruction, addr), marker))
+                }).collect()
+            },
+            STACK => {
+                let top = cpu.reg[6] as Address;
+                (0..STACK_WORDS).map(|offset| {
+                    let addr = top.wrapping_add(offset);
+                    variable(&format!("x{:04X}", addr), word(cpu.mem.peek(addr)))
+                }).collect()
+            },
+            DEVICE_REGISTERS => DEVICES.iter()
+                .map(|&(name, addr)| variable(name, format!("x{:04X}", cpu.mem.peek(addr) as u16)))
+                .collect(),
+            _ => Vec::new()
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    //////// RUNNING ////////
+
+    fn run(&mut self, motion: Motion) -> Failable<()> {
+        let result = self.run_until_stopped(motion);
+        self.send_output()?;
+
+        match result {
+            Ok(Some(reason)) => self.report(&reason),
+            Ok(None) => self.stopped("pause", None),
+            Err(error) => {
+                let pc = self.session()?.debugger.cpu.pc;
+                self.stopped("exception", Some(format!("Program faulted at x{:04X}: {}", pc, error)))
+            }
+        }
+    }
+
+    // Returns None if the editor paused the program.
+    fn run_until_stopped(&mut self, motion: Motion) -> Failable<Option<StopReason>> {
+        let depth = self.session()?.debugger.call_depth();
+
+        // stepping always executes something, even a call (which next then runs to the end of)
+        let target = match motion {
+            Motion::Continue => None,
+            Motion::StepOut => Some(depth - 1),
+            Motion::StepIn | Motion::StepOver => {
+                let debugger = &mut self.session()?.debugger;
+                if !debugger.cpu.running { return Ok(Some(StopReason::Halted)) }
+                if let Some(reason) = debugger.step()? { return Ok(Some(reason)) }
+                if !debugger.cpu.running { return Ok(Some(StopReason::Halted)) }
+                if motion == Motion::StepIn { return Ok(Some(StopReason::StepComplete)) }
+                Some(depth)
+            }
+        };
+
+        loop {
+            let debugger = &mut self.session()?.debugger;
+            if !debugger.cpu.running { return Ok(Some(StopReason::Halted)) }
+
+            let stopped = match target {
+                Some(depth) => debugger.continue_to_depth(depth, INSTRUCTIONS_PER_POLL)?,
+                None => debugger.continue_many(INSTRUCTIONS_PER_POLL)?
+            };
+            if stopped.is_some() { return Ok(stopped) }
+
+            self.send_output()?;
+            if self.pause_requested()? { return Ok(None) }
+        }
+    }
+
+    // Looks through what arrived while the program was running.  A pause
+    // (or a disconnect) stops the program, and what's typed in the debug
+    // console goes straight to it; every other request waits its turn.
+    fn pause_requested(&mut self) -> Failable<bool> {
+        loop {
+            match self.requests.try_recv() {
+                Ok(request) => {
+                    if request["command"] == "pause" {
+                        self.respond(&request, Ok(Value::Null))?;
+                        return Ok(true)
+                    }
+                    // the program gets to read what was typed before anything after it is looked at
+                    if request["command"] == "evaluate" {
+                        let body = self.evaluate(&request["arguments"]);
+                        self.respond(&request, body)?;
+                        return Ok(false)
+                    }
+
+                    let disconnecting = request["command"] == "disconnect";
+                    self.queued.push_back(request);
+                    if disconnecting { return Ok(true) }
+                },
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => return Ok(true)
+            }
+        }
+    }
+
+    fn report(&mut self, reason: &StopReason) -> Failable<()> {
+        match *reason {
+            StopReason::Breakpoint(_) => self.stopped("breakpoint", None),
+            StopReason::Watchpoint { index, access, .. } => {
+                self.stopped("data breakpoint", Some(format!("Watchpoint {} hit at x{:04X}", index, access.address)))
+            },
+            StopReason::Halted => {
+                // the operating system's exception handlers halt the machine too
+                let exit_code = if self.session()?.debugger.cpu.last_exception.is_some() { 1 } else { 0 };
+                self.send_event("exited", json!({ "exitCode": exit_code }))?;
+                self.send_event("terminated", Value::Null)
+            },
+            StopReason::StartOfHistory => self.stopped("step", Some("Reached the oldest recorded instruction".to_owned())),
+            StopReason::StepComplete => self.stopped("step", None)
+        }
+    }
+
+    //////// MESSAGES ////////
+
+    fn session(&mut self) -> Failable<&mut Session> {
+        self.session.as_mut().ok_or(LC3Error::NoProgramLaunched)
+    }
+
+    fn respond(&mut self, request: &Value, body: Failable<Value>) -> Failable<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok()
+        });
+
+        match body {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(error) => response["message"] = json!(error.to_string())
+        }
+
+        self.send(response)
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> Failable<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() { message["body"] = body }
+        self.send(message)
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> Failable<()> {
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["description"] = json!(text);
+            body["text"] = json!(text);
+        }
+        self.send_event("stopped", body)
+    }
+
+    // Passes along whatever the program has printed.
+    fn send_output(&mut self) -> Failable<()> {
+        let output = match self.session {
+            Some(ref session) => session.console.output_string(),
+            None => return Ok(())
+        };
+        if output.is_empty() { return Ok(()) }
+
+        self.session()?.console.clear_output();
+        self.send_event("output", json!({ "category": "stdout", "output": output }))
+    }
+
+    fn send(&mut self, mut message: Value) -> Failable<()> {
+        self.sequence += 1;
+        message["seq"] = json!(self.sequence);
+
+        let content = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn region(name: &str, start: Address, length: Address, reference: u64) -> Value {
+    let end = start.wrapping_add(length - 1);
+    json!({ "name": name, "value": format!("x{:04X} - x{:04X}", start, end), "variablesReference": reference })
+}
+
+fn word(value: Word) -> String {
+    format!("x{:04X} ({})", value as u16, value)
+}
+
+// Sends each message on until the input ends or the server has gone away.
+// Anything that isn't JSON is skipped.
+fn read_messages<R: BufRead>(mut input: R, sender: &Sender<Value>) {
+    while let Ok(Some(content)) = read_message(&mut input) {
+        if let Ok(message) = serde_json::from_slice(&content) {
+            if sender.send(message).is_err() { break }
+        }
+    }
+}
+
+// Headers, a blank line, then Content-Length bytes of JSON.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 { return Ok(None) }
+
+        let line = line.trim_end();
+        match length {
+            Some(_) if line.is_empty() => break,
+            _ => if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut content = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut content)?;
+    Ok(Some(content))
+}
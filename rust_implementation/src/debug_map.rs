@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use assembler::{parse_number, SourceMap};
+use cpu::Address;
+use errors::{Failable, LC3Error};
+
+//////////////////////////////////////////////////////
+// DEBUG MAP
+//////////////////////////////////////////////////////
+
+// Where each word of a program came from in its source, so that a debugger
+// can talk about file.asm:42 rather than x3005.  The assembler writes one
+// next to the program it assembles, one line per word:
+//
+//   // LC3 debug map
+//   x3000 program.asm:2
+//   x3001 program.asm:3
+//
+// Source files are relative to the map's own directory unless they're absolute.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: usize
+}
+
+impl fmt::Display for SourceLocation {
+    // Just the file's name; the whole path is rarely worth the space.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.file.file_name().map_or_else(|| self.file.to_string_lossy(), |name| name.to_string_lossy());
+        write!(f, "{}:{}", name, self.line)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DebugMap {
+    locations: BTreeMap<Address, SourceLocation>
+}
+
+impl DebugMap {
+    pub fn new() -> DebugMap {
+        DebugMap::default()
+    }
+
+    // Puts the source file the assembler's source map came from on each of its lines.
+    pub fn from_source_map<P: AsRef<Path>>(file: P, source_map: &SourceMap) -> DebugMap {
+        let file = absolute(file.as_ref());
+        let locations = source_map.iter()
+            .map(|(&addr, &line)| (addr, SourceLocation { file: file.clone(), line }))
+            .collect();
+
+        DebugMap { locations }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(filename: P) -> Failable<DebugMap> {
+        let contents = fs::read_to_string(&filename)?;
+        let directory = filename.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        DebugMap::from_string(&contents, directory)
+    }
+
+    // Relative source files are taken to be in the given directory.
+    pub fn from_string(contents: &str, directory: &Path) -> Failable<DebugMap> {
+        let mut locations = BTreeMap::new();
+
+        for (index, text) in contents.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with("//") { continue }
+
+            let location = parse_entry(text).ok_or(LC3Error::BadDebugMap(index + 1))?;
+            let (addr, file, line) = location;
+            locations.insert(addr, SourceLocation { file: absolute(&directory.join(file)), line });
+        }
+
+        Ok(DebugMap { locations })
+    }
+
+    // Source files under the map's directory are written relative to it.
+    pub fn write_file<P: AsRef<Path>>(&self, filename: P) -> Failable<()> {
+        let directory = absolute(filename.as_ref().parent().unwrap_or_else(|| Path::new("")));
+        let mut contents = "// LC3 debug map\n".to_owned();
+
+        for (addr, location) in &self.locations {
+            let file = location.file.strip_prefix(&directory).unwrap_or(&location.file);
+            contents.push_str(&format!("x{:04X} {}:{}\n", addr, file.display(), location.line));
+        }
+
+        fs::write(filename, contents)?;
+        Ok(())
+    }
+
+    pub fn extend(&mut self, other: &DebugMap) {
+        self.locations.extend(other.locations.iter().map(|(&addr, location)| (addr, location.clone())));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn location(&self, addr: Address) -> Option<&SourceLocation> {
+        self.locations.get(&addr)
+    }
+
+    // Where a breakpoint on the file's line goes: the first address of that
+    // line, or of the next one with any code.  Returns the line it ends up on
+    // as well.  The file can be given as any path to it, or just as the end
+    // of one (like its name).
+    pub fn resolve(&self, file: &str, line: usize) -> Option<(usize, Address)> {
+        let given = absolute(Path::new(file));
+
+        self.locations.iter()
+            .filter(|&(_, location)| location.line >= line)
+            .filter(|&(_, location)| location.file == given || location.file.ends_with(file))
+            .map(|(&addr, location)| (location.line, addr))
+            .min()
+    }
+}
+
+// x3000 file.asm:12 => (x3000, file.asm, 12).  The line number comes after
+// the last colon so that Windows paths keep theirs.
+fn parse_entry(text: &str) -> Option<(Address, &str, usize)> {
+    let mut fields = text.splitn(2, char::is_whitespace);
+    let addr = fields.next().and_then(parse_number).filter(|&addr| (0..=0xFFFF).contains(&addr))?;
+
+    let (file, line) = fields.next()?.trim().rsplit_once(':')?;
+    let line = line.parse().ok()?;
+    Some((addr as Address, file, line))
+}
+
+// Paths are compared once they're absolute, when the file is there to make them so.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,428 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use condition_code::ConditionCode;
+use cpu::{AccessKind, Address, Instruction, MemoryAccess, TrapMode, Word, CPU};
+use debug_map::DebugMap;
+use memory::DeviceState;
+use errors::Failable;
+use privilege::Privilege;
+use symbols::Symbols;
+use trace::{TraceEntry, TraceWriter};
+use utils::UnsignedBitSelection;
+
+//////////////////////////////////////////////////////
+// DEBUGGER
+//////////////////////////////////////////////////////
+
+// Wraps a CPU with the state a person debugging it cares about (breakpoints
+// and friends) so that the CPU itself only has to know how to execute.
+
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    Breakpoint(Address),
+    // pc and ir describe the instruction that made the access
+    Watchpoint { index: usize, access: MemoryAccess, pc: Address, ir: Instruction },
+    Halted,
+    // stepping backwards ran out of recorded history
+    StartOfHistory,
+    // the program got back out to the call depth it was run to
+    StepComplete
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind { Read, Write, Access }
+
+// Watches every address from start to end (inclusive).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: Address,
+    pub end: Address,
+    pub kind: WatchKind
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind_matches = matches!((self.kind, access.kind),
+            (WatchKind::Access, _) | (WatchKind::Read, AccessKind::Read) | (WatchKind::Write, AccessKind::Write));
+
+        kind_matches && access.address >= self.start && access.address <= self.end
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access"
+        };
+
+        if self.start == self.end {
+            write!(f, "{} x{:04X}", kind, self.start)
+        } else {
+            write!(f, "{} x{:04X}-x{:04X}", kind, self.start, self.end)
+        }
+    }
+}
+
+// How many instructions can be stepped back by default.
+pub const DEFAULT_HISTORY_SIZE: usize = 10_000;
+
+// What an instruction cycle changed, so that it can be undone: the processor
+// and device state from before the cycle and the old contents of each RAM
+// address it wrote.  A key the program read is given back to the keyboard, but
+// a printed character can't be taken back, and interrupts that were pending
+// aren't restored.
+struct UndoRecord {
+    reg: Vec<Word>,
+    pc: Address,
+    ir: Instruction,
+    cc: ConditionCode,
+    privilege: Privilege,
+    priority: u8,
+    saved_ssp: Word,
+    saved_usp: Word,
+    running: bool,
+    last_exception: Option<u8>,
+    call_depth: isize,
+    devices: Vec<DeviceState>,
+    writes: Vec<(Address, Word)>
+}
+
+impl UndoRecord {
+    fn before(cpu: &CPU, call_depth: isize) -> UndoRecord {
+        UndoRecord {
+            reg: cpu.reg.vals.clone(),
+            pc: cpu.pc,
+            ir: cpu.ir,
+            cc: cpu.cc,
+            privilege: cpu.privilege,
+            priority: cpu.priority,
+            saved_ssp: cpu.saved_ssp,
+            saved_usp: cpu.saved_usp,
+            running: cpu.running,
+            last_exception: cpu.last_exception,
+            call_depth,
+            devices: cpu.mem.save_device_state(),
+            writes: Vec::new()
+        }
+    }
+
+    fn restore(self, cpu: &mut CPU) {
+        // undo the writes newest first so an address written twice ends up with its oldest value
+        for (address, previous) in self.writes.into_iter().rev() {
+            cpu.mem[address] = previous;
+        }
+        cpu.mem.restore_device_state(&self.devices);
+
+        cpu.reg.vals = self.reg;
+        cpu.ir = self.ir;
+        cpu.cc = self.cc;
+        cpu.privilege = self.privilege;
+        cpu.priority = self.priority;
+        cpu.saved_ssp = self.saved_ssp;
+        cpu.saved_usp = self.saved_usp;
+        cpu.last_exception = self.last_exception;
+        cpu.accesses.clear();
+
+        // restarting the clock undoes a HALT
+        if self.running {
+            cpu.set_pc_address(self.pc);
+        } else {
+            cpu.pc = self.pc;
+            cpu.running = false;
+        }
+    }
+}
+
+pub struct Debugger {
+    pub cpu: CPU,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: Vec<Watchpoint>,
+    history: VecDeque<UndoRecord>,
+    history_size: usize,
+    trace: Option<TraceWriter>,
+    call_depth: isize,
+    symbols: Symbols,
+    debug_map: DebugMap
+}
+
+impl Debugger {
+    pub fn new(cpu: CPU) -> Debugger {
+        Debugger {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            history: VecDeque::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            trace: None,
+            call_depth: 0,
+            symbols: Symbols::new(),
+            debug_map: DebugMap::new()
+        }
+    }
+
+    // Returns false if there was already a breakpoint at the address.
+    pub fn add_breakpoint(&mut self, addr: Address) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    // Returns false if there was no breakpoint at the address.
+    pub fn remove_breakpoint(&mut self, addr: Address) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<Address> {
+        &self.breakpoints
+    }
+
+    // Returns the index used to refer to the new watchpoint.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Labels for the loaded programs, so they can be referred to by name.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn add_symbols(&mut self, symbols: &Symbols) {
+        self.symbols.extend(symbols);
+    }
+
+    // Where the loaded programs' instructions came from in their source.
+    pub fn debug_map(&self) -> &DebugMap {
+        &self.debug_map
+    }
+
+    pub fn add_debug_map(&mut self, debug_map: &DebugMap) {
+        self.debug_map.extend(debug_map);
+    }
+
+    // Runs a single instruction, reporting any watchpoint that it triggered.
+    pub fn step(&mut self) -> Failable<Option<StopReason>> {
+        let pc = self.cpu.pc;
+        let mut record = UndoRecord::before(&self.cpu, self.call_depth);
+        self.cpu.run_one_instruction_cycle()?;
+        self.call_depth += call_depth_change(&record, &self.cpu);
+
+        if let Some(ref mut trace) = self.trace {
+            trace.write_entry(&TraceEntry::capture(pc, &self.cpu))?;
+        }
+
+        record.writes = self.cpu.accesses.iter()
+            .filter(|access| access.kind == AccessKind::Write && !self.cpu.mem.handles(access.address))
+            .map(|access| (access.address, access.previous))
+            .collect();
+
+        // native GETC and IN take their character straight from the console,
+        // which can't be given it back, so there's no stepping back past them
+        if reads_the_console(&self.cpu) {
+            self.history.clear();
+        } else {
+            self.remember(record);
+        }
+
+        // entering an interrupt executes no instruction that its pushes onto
+        // the supervisor stack could be blamed on
+        if self.cpu.interrupt_taken.is_some() { return Ok(None) }
+        Ok(self.triggered_watchpoint(pc))
+    }
+
+    // Runs up to count instructions, stopping early if a watchpoint is
+    // triggered or the CPU halts.
+    pub fn step_many(&mut self, count: u32) -> Failable<Option<StopReason>> {
+        for _ in 0..count {
+            if let Some(reason) = self.step()? { return Ok(Some(reason)) }
+            if !self.cpu.running { return Ok(Some(StopReason::Halted)) }
+        }
+
+        Ok(None)
+    }
+
+    // Runs until the PC lands on a breakpoint, a watchpoint is triggered, or
+    // the CPU halts.  At least one instruction is always executed so that
+    // continuing from a breakpoint doesn't immediately stop on it again.
+    pub fn continue_execution(&mut self) -> Failable<StopReason> {
+        loop {
+            if let Some(reason) = self.continue_many(u32::MAX)? { return Ok(reason) }
+        }
+    }
+
+    // Continues for at most count instructions, returning None if nothing
+    // stopped it, so that a caller can check for other events in between.
+    pub fn continue_many(&mut self, count: u32) -> Failable<Option<StopReason>> {
+        for _ in 0..count {
+            if let Some(reason) = self.step()? { return Ok(Some(reason)) }
+
+            if !self.cpu.running { return Ok(Some(StopReason::Halted)) }
+            if self.breakpoints.contains(&self.cpu.pc) { return Ok(Some(StopReason::Breakpoint(self.cpu.pc))) }
+        }
+
+        Ok(None)
+    }
+
+    // How many subroutines and service routines deep the program is, counted
+    // from wherever the debugger started (so it can be negative).
+    pub fn call_depth(&self) -> isize {
+        self.call_depth
+    }
+
+    // Continues for at most count instructions until the call depth is at
+    // most depth, which finishes a step over a call (run to the depth before
+    // it) or out of one (run to one less than the current depth).  Returns
+    // None if it hasn't got there yet and nothing else stopped it.
+    pub fn continue_to_depth(&mut self, depth: isize, count: u32) -> Failable<Option<StopReason>> {
+        for _ in 0..count {
+            if self.call_depth <= depth { return Ok(Some(StopReason::StepComplete)) }
+            if let Some(reason) = self.step()? { return Ok(Some(reason)) }
+
+            if !self.cpu.running { return Ok(Some(StopReason::Halted)) }
+            if self.breakpoints.contains(&self.cpu.pc) { return Ok(Some(StopReason::Breakpoint(self.cpu.pc))) }
+        }
+
+        Ok(None)
+    }
+
+    // Runs a single instruction, except that a call (JSR, JSRR, or a TRAP
+    // into the operating system) is run until it returns to the same depth,
+    // so the whole subroutine counts as one step.  Breakpoints and
+    // watchpoints inside it still stop it.
+    pub fn step_over(&mut self) -> Failable<StopReason> {
+        let depth = self.call_depth;
+
+        loop {
+            if let Some(reason) = self.step()? { return Ok(reason) }
+            if !self.cpu.running { return Ok(StopReason::Halted) }
+
+            // an interrupt taken first is run through, and then the instruction is stepped over
+            let interrupted = self.cpu.interrupt_taken.is_some();
+            match self.run_to_depth(depth)? {
+                StopReason::StepComplete if interrupted => continue,
+                reason => return Ok(reason)
+            }
+        }
+    }
+
+    // Runs until the current subroutine (or service routine) returns to
+    // whatever called it with RET or RTI.
+    pub fn step_out(&mut self) -> Failable<StopReason> {
+        let depth = self.call_depth - 1;
+        self.run_to_depth(depth)
+    }
+
+    fn run_to_depth(&mut self, depth: isize) -> Failable<StopReason> {
+        loop {
+            if let Some(reason) = self.continue_to_depth(depth, u32::MAX)? { return Ok(reason) }
+        }
+    }
+
+    // Every instruction stepped from now on is written to the trace (None stops tracing).
+    pub fn set_trace(&mut self, trace: Option<TraceWriter>) -> Failable<()> {
+        if let Some(ref mut old) = self.trace { old.flush()? }
+        self.trace = trace;
+        Ok(())
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    //////// REVERSE EXECUTION ////////
+
+    // Undoes up to count instructions, returning how many were undone.
+    pub fn step_back(&mut self, count: usize) -> usize {
+        for undone in 0..count {
+            match self.history.pop_back() {
+                Some(record) => {
+                    self.call_depth = record.call_depth;
+                    record.restore(&mut self.cpu)
+                },
+                None => return undone
+            }
+        }
+
+        count
+    }
+
+    // Runs backwards until the PC lands on a breakpoint or there is no more
+    // history.  At least one instruction is always undone.
+    pub fn reverse_continue(&mut self) -> StopReason {
+        while self.step_back(1) == 1 {
+            if self.breakpoints.contains(&self.cpu.pc) { return StopReason::Breakpoint(self.cpu.pc) }
+        }
+
+        StopReason::StartOfHistory
+    }
+
+    // How many instructions can currently be stepped back.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn history_size(&self) -> usize {
+        self.history_size
+    }
+
+    // Limits how many instructions are remembered (0 turns reverse execution off).
+    pub fn set_history_size(&mut self, size: usize) {
+        self.history_size = size;
+        while self.history.len() > size {
+            self.history.pop_front();
+        }
+    }
+
+    fn remember(&mut self, record: UndoRecord) {
+        if self.history_size == 0 { return }
+        if self.history.len() == self.history_size {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+    }
+
+    fn triggered_watchpoint(&self, pc: Address) -> Option<StopReason> {
+        for access in &self.cpu.accesses {
+            if let Some(index) = self.watchpoints.iter().position(|watchpoint| watchpoint.matches(access)) {
+                return Some(StopReason::Watchpoint { index, access: *access, pc, ir: self.cpu.ir })
+            }
+        }
+
+        None
+    }
+}
+
+// How an instruction cycle changed the call depth.  JSR, JSRR, TRAPs into the
+// operating system, interrupts, and exceptions go a level deeper; RET and RTI
+// come back out.
+fn reads_the_console(cpu: &CPU) -> bool {
+    cpu.interrupt_taken.is_none() && cpu.trap_mode == TrapMode::Native &&
+        cpu.ir.bits(15, 12) == 0b1111 && (cpu.ir.bits(7, 0) == 0x20 || cpu.ir.bits(7, 0) == 0x23)
+}
+
+fn call_depth_change(before: &UndoRecord, cpu: &CPU) -> isize {
+    // entering an interrupt is a cycle of its own, with no instruction
+    if cpu.interrupt_taken.is_some() { return 1 }
+
+    match cpu.ir.bits(15, 12) {
+        0b0100 => 1, // JSR and JSRR
+        0b1111 if cpu.trap_mode == TrapMode::OperatingSystem => 1,
+        0b1100 if cpu.ir.bits(8, 6) == 7 => -1, // RET
+        0b1000 if before.privilege == Privilege::User => 1, // RTI from user mode is a privilege violation
+        0b1000 => -1,
+        0b1101 => 1, // illegal opcode exception
+        _ => 0
+    }
+}
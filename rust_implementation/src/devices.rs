@@ -0,0 +1,229 @@
+use console::Console;
+use cpu::{Address, Word};
+use errors::{Failable, LC3Error};
+use interrupt::InterruptController;
+use memory::{Device, DeviceState};
+
+//////////////////////////////////////////////////////
+// STANDARD LC3 DEVICES
+//////////////////////////////////////////////////////
+
+pub const KBSR: Address = 0xFE00; // Keyboard Status Register
+pub const KBDR: Address = 0xFE02; // Keyboard Data Register
+pub const DSR: Address = 0xFE04;  // Display Status Register
+pub const DDR: Address = 0xFE06;  // Display Data Register
+pub const MCR: Address = 0xFFFE;  // Machine Control Register
+
+pub const KEYBOARD_INTERRUPT_VECTOR: u8 = 0x80;
+pub const KEYBOARD_INTERRUPT_PRIORITY: u8 = 4;
+
+pub const CLOCK_ENABLE_BIT: Word = 1 << 15;
+const READY_BIT: Word = 1 << 15;
+const INTERRUPT_ENABLE_BIT: Word = 1 << 14;
+
+
+// KBSR[15] is set while a key is waiting in KBDR; reading KBDR consumes it.
+// KBSR[14] enables keyboard interrupts.
+//
+// Every key is kept after it's read, so that stepping backwards over a read
+// of KBDR can give the key back just by moving back to it.
+pub struct Keyboard {
+    keys: Vec<u8>,
+    next_key: usize,
+    interrupt_enabled: bool
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard {
+            keys: Vec::new(),
+            next_key: 0,
+            interrupt_enabled: false
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys.push(key);
+    }
+
+    fn waiting(&self) -> Option<u8> {
+        self.keys.get(self.next_key).cloned()
+    }
+
+    fn status(&self) -> Word {
+        let ready = if self.waiting().is_none() { 0 } else { READY_BIT };
+        let enabled = if self.interrupt_enabled { INTERRUPT_ENABLE_BIT } else { 0 };
+        ready | enabled
+    }
+
+    // A program reading the status register is told at once whether a key is
+    // waiting; only reading KBDR takes it.  Once the console's input has run
+    // out for good no key is ever coming, and the program would poll forever.
+    fn poll_for_key(&mut self, console: &mut dyn Console) -> Failable<()> {
+        if self.waiting().is_some() { return Ok(()) }
+
+        match console.poll_byte() {
+            Some(key) => self.press(key),
+            None if console.input_closed() => return Err(LC3Error::EndOfInput),
+            None => ()
+        }
+        Ok(())
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+impl Device for Keyboard {
+    fn handles(&self, addr: Address) -> bool {
+        addr == KBSR || addr == KBDR
+    }
+
+    fn read(&mut self, addr: Address, console: &mut dyn Console) -> Failable<Word> {
+        if addr == KBSR {
+            self.poll_for_key(console)?;
+            Ok(self.status())
+        } else {
+            let key = self.waiting();
+            if key.is_some() { self.next_key += 1 }
+            Ok(key.map_or(0, Word::from))
+        }
+    }
+
+    fn write(&mut self, addr: Address, value: Word, _console: &mut dyn Console) {
+        if addr == KBSR {
+            self.interrupt_enabled = value & INTERRUPT_ENABLE_BIT != 0;
+        }
+    }
+
+    fn peek(&self, addr: Address) -> Word {
+        if addr == KBSR {
+            self.status()
+        } else {
+            self.waiting().map_or(0, Word::from)
+        }
+    }
+
+    // Keys that arrived since are kept, still waiting behind the ones given back.
+    fn save_state(&self) -> DeviceState {
+        vec![self.next_key, self.interrupt_enabled as usize]
+    }
+
+    fn restore_state(&mut self, state: &[usize]) {
+        self.next_key = state[0];
+        self.interrupt_enabled = state[1] != 0;
+    }
+
+    // Keys that have already been typed are picked up here so that they can
+    // interrupt a program that isn't polling for them.
+    fn tick(&mut self, interrupts: &mut InterruptController, console: &mut dyn Console) {
+        if self.interrupt_enabled && self.waiting().is_none() {
+            if let Some(key) = console.poll_byte() { self.press(key) }
+        }
+
+        if self.interrupt_enabled && self.waiting().is_some() {
+            interrupts.raise(KEYBOARD_INTERRUPT_VECTOR, KEYBOARD_INTERRUPT_PRIORITY);
+        } else {
+            interrupts.clear(KEYBOARD_INTERRUPT_VECTOR);
+        }
+    }
+}
+
+
+// The display is always ready (DSR[15]); each character written to DDR goes to the console.
+pub struct Display {
+    last_written: Word
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            last_written: 0
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Device for Display {
+    fn handles(&self, addr: Address) -> bool {
+        addr == DSR || addr == DDR
+    }
+
+    fn read(&mut self, addr: Address, _console: &mut dyn Console) -> Failable<Word> {
+        Ok(self.peek(addr))
+    }
+
+    fn write(&mut self, addr: Address, value: Word, console: &mut dyn Console) {
+        if addr == DDR {
+            self.last_written = value;
+            console.write_bytes(&[value as u8]).ok();
+        }
+    }
+
+    fn peek(&self, addr: Address) -> Word {
+        if addr == DSR { READY_BIT } else { self.last_written }
+    }
+
+    // What was printed stays printed; only DDR goes back to its old value.
+    fn save_state(&self) -> DeviceState {
+        vec![self.last_written as u16 as usize]
+    }
+
+    fn restore_state(&mut self, state: &[usize]) {
+        self.last_written = state[0] as Word;
+    }
+}
+
+
+// MCR[15] is the machine's clock; clearing it stops the machine.
+pub struct MachineControl {
+    mcr: Word
+}
+
+impl MachineControl {
+    pub fn new() -> MachineControl {
+        MachineControl {
+            mcr: CLOCK_ENABLE_BIT
+        }
+    }
+}
+
+impl Default for MachineControl {
+    fn default() -> MachineControl {
+        MachineControl::new()
+    }
+}
+
+impl Device for MachineControl {
+    fn handles(&self, addr: Address) -> bool {
+        addr == MCR
+    }
+
+    fn read(&mut self, _addr: Address, _console: &mut dyn Console) -> Failable<Word> {
+        Ok(self.mcr)
+    }
+
+    fn write(&mut self, _addr: Address, value: Word, _console: &mut dyn Console) {
+        self.mcr = value;
+    }
+
+    fn peek(&self, _addr: Address) -> Word {
+        self.mcr
+    }
+
+    fn save_state(&self) -> DeviceState {
+        vec![self.mcr as u16 as usize]
+    }
+
+    fn restore_state(&mut self, state: &[usize]) {
+        self.mcr = state[0] as Word;
+    }
+}
@@ -0,0 +1,110 @@
+use cpu::{Address, Instruction};
+use operation::Operation;
+use program::Program;
+use symbols::{describe_address, Symbols};
+use utils::{SignedBitSelection, UnsignedBitSelection};
+
+//////////////////////////////////////////////////////
+// LC3 DISASSEMBLER
+//////////////////////////////////////////////////////
+
+// Renders a single word as LC3 assembly.  The address of the word is needed
+// to turn PC-relative offsets back into the absolute addresses they refer to.
+pub fn disassemble(instr: Instruction, address: Address) -> String {
+    disassemble_with_symbols(instr, address, &Symbols::new())
+}
+
+// The same, with the addresses PC-relative instructions refer to followed by
+// their labels: BRz x3007 (LOOP+2)
+pub fn disassemble_with_symbols(instr: Instruction, address: Address, symbols: &Symbols) -> String {
+    let op_code = instr.bits(15, 12) as u8;
+    let operation = match Operation::from_code(op_code) {
+        Ok(operation) => operation,
+        Err(_) => return fill(instr)
+    };
+
+    let dr = instr.bits(11, 9);
+    let sr1 = instr.bits(8, 6);
+
+    match operation {
+        Operation::BR => {
+            let nzp = instr.bits(11, 9);
+            if nzp == 0 { return "NOP".to_owned() }
+
+            let mut mnemonic = "BR".to_owned();
+            if nzp != 0b111 {
+                if nzp & 0b100 != 0 { mnemonic.push('n') }
+                if nzp & 0b010 != 0 { mnemonic.push('z') }
+                if nzp & 0b001 != 0 { mnemonic.push('p') }
+            }
+            format!("{} {}", mnemonic, pc_target(instr, address, 9, symbols))
+        },
+        Operation::ADD => format!("ADD R{}, R{}, {}", dr, sr1, second_operand(instr)),
+        Operation::AND => format!("AND R{}, R{}, {}", dr, sr1, second_operand(instr)),
+        Operation::NOT => format!("NOT R{}, R{}", dr, sr1),
+        Operation::LD => format!("LD R{}, {}", dr, pc_target(instr, address, 9, symbols)),
+        Operation::LDI => format!("LDI R{}, {}", dr, pc_target(instr, address, 9, symbols)),
+        Operation::LEA => format!("LEA R{}, {}", dr, pc_target(instr, address, 9, symbols)),
+        Operation::ST => format!("ST R{}, {}", dr, pc_target(instr, address, 9, symbols)),
+        Operation::STI => format!("STI R{}, {}", dr, pc_target(instr, address, 9, symbols)),
+        Operation::LDR => format!("LDR R{}, R{}, #{}", dr, sr1, instr.bits_signed(5, 0)),
+        Operation::STR => format!("STR R{}, R{}, #{}", dr, sr1, instr.bits_signed(5, 0)),
+        Operation::JSR => {
+            if instr.bits(11, 11) == 1 {
+                format!("JSR {}", pc_target(instr, address, 11, symbols))
+            } else {
+                format!("JSRR R{}", sr1)
+            }
+        },
+        Operation::JMP => {
+            if sr1 == 7 { "RET".to_owned() } else { format!("JMP R{}", sr1) }
+        },
+        Operation::RTI => "RTI".to_owned(),
+        Operation::TRAP => {
+            match instr.bits(7, 0) {
+                0x20 => "GETC".to_owned(),
+                0x21 => "OUT".to_owned(),
+                0x22 => "PUTS".to_owned(),
+                0x23 => "IN".to_owned(),
+                0x24 => "PUTSP".to_owned(),
+                0x25 => "HALT".to_owned(),
+                trap_vector => format!("TRAP x{:02X}", trap_vector)
+            }
+        },
+        Operation::ERR => fill(instr)
+    }
+}
+
+// Disassembles every word of a program, one "address: word  assembly" line each.
+pub fn disassemble_program(program: &Program, symbols: &Symbols) -> Vec<String> {
+    let mut address = program.program_counter_start();
+    let mut lines = Vec::new();
+
+    for &instr in program.instructions() {
+        lines.push(format!("{}: x{:04X}    {}", describe_address(address, symbols), instr,
+                           disassemble_with_symbols(instr, address, symbols)));
+        address = address.wrapping_add(1);
+    }
+
+    lines
+}
+
+// The last operand of ADD/AND is either a register or a 5 bit immediate.
+fn second_operand(instr: Instruction) -> String {
+    if instr.bits(5, 5) == 0 {
+        format!("R{}", instr.bits(2, 0))
+    } else {
+        format!("#{}", instr.bits_signed(4, 0))
+    }
+}
+
+fn pc_target(instr: Instruction, address: Address, offset_bits: i32, symbols: &Symbols) -> String {
+    let offset = instr.bits_signed(offset_bits - 1, 0);
+    let target = address.wrapping_add(1).wrapping_add(offset as Address);
+    describe_address(target, symbols)
+}
+
+// Words that don't decode to a real instruction are shown as data.
+fn fill(instr: Instruction) -> String {
+    format!(".FILL x{:04X}", instr)
+}
@@ -1,3 +1,6 @@
+// failure_derive predates the non-local impl lint.
+#![allow(non_local_definitions)]
+
 use std;
 
 #[derive(Fail, Debug)]
@@ -13,18 +16,34 @@ pub enum LC3Error {
     BadProgramHeader,
     #[fail(display = "file has an invalid instructions")]
     BadProgramInstructions,
+    #[fail(display = "object file is truncated; it must contain at least an origin word")]
+    ObjectFileTruncated,
+    #[fail(display = "object file has an odd length of {} bytes; it must contain whole 16-bit words", _0)]
+    ObjectFileOddLength(usize),
+    #[fail(display = "assembly failed on line {}: {}", _0, _1)]
+    AssemblyError(usize, String),
+    #[fail(display = "debug map line {} is not an address followed by file:line", _0)]
+    BadDebugMap(usize),
     #[fail(display = "failed operation; given bad arguement")]
     BadArguement,
     #[fail(display = "failed operation; missing arguement")]
     CommandMissingArguement,
     #[fail(display = "{} is not a recognized command", _0)]
     UnrecognizedConsoleCommand(String),
+    #[fail(display = "there is no code at or after {}:{}", _0, _1)]
+    NoCodeAtLine(String, usize),
     #[fail(display = "invalid register; the choices are r0 - r7")]
     InvalidRegister,
     #[fail(display = "CPU is not currently running; unable to run instruction")]
     CpuNotRunning,
-    #[fail(display = "This implementation does not support the RTI opcode")]
-    RTINotSupported,
+    #[fail(display = "the program asked for input but there is none left")]
+    EndOfInput,
+    #[fail(display = "the string at x{:04X} runs off the end of memory without a terminator", _0)]
+    UnterminatedString(u16),
+    #[fail(display = "no program has been launched")]
+    NoProgramLaunched,
+    #[fail(display = "interrupt vector x{:02X} has no service routine", _0)]
+    MissingServiceRoutine(u8),
     #[fail(display = "Tried calling an unused op code")]
     UnusedOpCode,
     #[fail(display = "This implementation does not support trap code {}", _0)]
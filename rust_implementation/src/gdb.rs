@@ -0,0 +1,537 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use cpu::{Address, Word, NUM_MEMORY_ADDRESSES};
+use debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+use errors::Failable;
+
+//////////////////////////////////////////////////////
+// GDB REMOTE SERIAL PROTOCOL
+//////////////////////////////////////////////////////
+
+// A stub that lets anything speaking GDB's remote serial protocol drive a
+// Debugger: registers, memory, stepping (forwards and backwards), continuing,
+// breakpoints, and watchpoints.
+//
+// The LC3 is word addressed, so both the addresses and the lengths in memory
+// and watchpoint packets count 16-bit words, and each word is sent as two
+// bytes, low byte first.  Registers are R0 - R7, then the PC, then the PSR,
+// each sent as a 16-bit little endian value.  The target description GDB can
+// ask for (target.xml) says the same.
+
+const NUM_GDB_REGISTERS: usize = 10;
+const PC_REGISTER: usize = 8;
+const PSR_REGISTER: usize = 9;
+
+// The largest packet GDB is told it may send, and so the most memory one
+// read or write can move: every word takes four hex digits.
+const PACKET_SIZE: usize = 0x1000;
+const MAX_TRANSFER_WORDS: usize = PACKET_SIZE / 4;
+
+// A watchpoint can cover at most all of memory.
+const MAX_WATCH_WORDS: usize = NUM_MEMORY_ADDRESSES as usize;
+
+const TARGET_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<!-- The LC-3 is word addressed: addresses and lengths in m, M, and Z packets
+     count 16-bit words, and each word is sent low byte first. -->
+<target version="1.0">
+  <feature name="org.lc3.core">
+    <reg name="r0" bitsize="16" type="int16" regnum="0"/>
+    <reg name="r1" bitsize="16" type="int16"/>
+    <reg name="r2" bitsize="16" type="int16"/>
+    <reg name="r3" bitsize="16" type="int16"/>
+    <reg name="r4" bitsize="16" type="int16"/>
+    <reg name="r5" bitsize="16" type="int16"/>
+    <reg name="r6" bitsize="16" type="data_ptr"/>
+    <reg name="r7" bitsize="16" type="code_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="psr" bitsize="16" type="uint16"/>
+  </feature>
+</target>
+"#;
+
+// How many instructions run between checks for an interrupt from GDB.
+const INSTRUCTIONS_PER_POLL: u32 = 4096;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// A connection to GDB.
+pub trait Transport: Read + Write {
+    // Reads whatever GDB has already sent without waiting for more, failing
+    // with WouldBlock if it hasn't sent anything (and giving 0 once it's gone).
+    fn read_available(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+}
+
+impl Transport for TcpStream {
+    fn read_available(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.set_nonblocking(true)?;
+        let result = self.read(buffer);
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn read_available(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.set_nonblocking(true)?;
+        let result = self.read(buffer);
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+pub struct GdbStub<'a, T: Transport> {
+    debugger: &'a mut Debugger,
+    transport: T,
+    acknowledge: bool, // packets are acknowledged until GDB asks for no-ack mode
+    received: VecDeque<u8> // bytes read while checking for an interrupt, still to be handled
+}
+
+impl<'a, T: Transport> GdbStub<'a, T> {
+    pub fn new(debugger: &'a mut Debugger, transport: T) -> GdbStub<'a, T> {
+        GdbStub { debugger, transport, acknowledge: true, received: VecDeque::new() }
+    }
+
+    // Answers packets until GDB detaches, kills the program, or hangs up.
+    pub fn serve(&mut self) -> Failable<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.write_packet(&reply)?,
+                None => break
+            }
+        }
+
+        Ok(())
+    }
+
+    //////// PACKETS ////////
+
+    // Returns the next packet's contents, or None once the connection is closed.
+    fn read_packet(&mut self) -> Failable<Option<String>> {
+        loop {
+            // skip acknowledgements and stray interrupts until a packet starts
+            match self.read_byte()? {
+                Some(b'$') => {},
+                Some(_) => continue,
+                None => return Ok(None)
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None)
+                }
+            }
+
+            let mut checksum = [0u8; 2];
+            for digit in &mut checksum {
+                *digit = self.read_byte()?.ok_or_else(|| io::Error::from(ErrorKind::UnexpectedEof))?;
+            }
+            let expected = ::std::str::from_utf8(&checksum).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            if expected == Some(packet_checksum(&data)) {
+                if self.acknowledge { self.transport.write_all(b"+")? }
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+            } else if self.acknowledge {
+                self.transport.write_all(b"-")?; // asks GDB to send it again
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Failable<Option<u8>> {
+        if let Some(byte) = self.received.pop_front() { return Ok(Some(byte)) }
+
+        let mut byte = [0u8; 1];
+        match self.transport.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0]))
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> Failable<()> {
+        let packet = format!("${}#{:02x}", data, packet_checksum(data.as_bytes()));
+        self.transport.write_all(packet.as_bytes())?;
+        self.transport.flush()?;
+
+        // GDB asks for a resend with '-'; anything else is taken as the ack,
+        // and kept if it's the start of the next packet
+        if self.acknowledge {
+            loop {
+                match self.read_byte()? {
+                    Some(b'-') => {
+                        self.transport.write_all(packet.as_bytes())?;
+                        self.transport.flush()?;
+                    },
+                    Some(b'+') | None => break,
+                    Some(byte) => {
+                        self.received.push_front(byte);
+                        break
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Whether GDB has asked to interrupt a running program (by sending ^C),
+    // without waiting for it to say anything.  Anything else it sent is kept
+    // for once the program stops, and a closed connection counts as an
+    // interrupt so that the program stops running.
+    fn interrupted(&mut self) -> Failable<bool> {
+        let mut buffer = [0u8; 256];
+        let length = match self.transport.read_available(&mut buffer) {
+            Ok(0) => return Ok(true),
+            Ok(length) => length,
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err.into())
+        };
+
+        let bytes = &buffer[..length];
+        self.received.extend(bytes.iter().filter(|&&byte| byte != 0x03));
+        Ok(bytes.contains(&0x03))
+    }
+
+    //////// COMMANDS ////////
+
+    // Returns the reply to send, or None if the session is over.  Commands
+    // the stub doesn't know get an empty reply, as the protocol asks.
+    fn handle(&mut self, packet: &str) -> Failable<Option<String>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match command {
+            "?" => stop_reply(SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" => self.step(args)?,
+            "c" => self.resume(args)?,
+            "b" => self.reverse(args),
+            "Z" => self.set_point(args, true),
+            "z" => self.set_point(args, false),
+            "H" => "OK".to_owned(),
+            "T" => "OK".to_owned(), // there is only ever one thread and it's alive
+            "q" => self.query(args),
+            "Q" => self.set_option(args),
+            "D" => {
+                self.write_packet("OK")?;
+                return Ok(None)
+            },
+            "k" => return Ok(None),
+            _ => String::new()
+        };
+
+        Ok(Some(reply))
+    }
+
+    fn query(&mut self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return format!("PacketSize={:x};QStartNoAckMode+;swbreak+;ReverseStep+;ReverseContinue+;qXfer:features:read+",
+                           PACKET_SIZE)
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            return read_target_description(range)
+        }
+
+        match args {
+            "Attached" => "1".to_owned(),
+            "C" => "QC1".to_owned(),
+            "fThreadInfo" => "m1".to_owned(),
+            "sThreadInfo" => "l".to_owned(),
+            _ => String::new()
+        }
+    }
+
+    fn set_option(&mut self, args: &str) -> String {
+        match args {
+            "StartNoAckMode" => {
+                self.acknowledge = false;
+                "OK".to_owned()
+            },
+            _ => String::new()
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..NUM_GDB_REGISTERS).map(|reg| encode_word(self.register(reg))).collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let values = match decode_bytes(args) {
+            Some(ref bytes) if bytes.len() == NUM_GDB_REGISTERS * 2 => words_from_bytes(bytes),
+            _ => return error_reply()
+        };
+
+        for (reg, value) in values.into_iter().enumerate() {
+            self.set_register(reg, value);
+        }
+        "OK".to_owned()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match parse_hex(args).filter(|&reg| reg < NUM_GDB_REGISTERS as u32) {
+            Some(reg) => encode_word(self.register(reg as usize)),
+            None => error_reply()
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, '=');
+        let reg = parts.next().and_then(parse_hex).filter(|&reg| reg < NUM_GDB_REGISTERS as u32);
+        let value = parts.next().and_then(decode_bytes).filter(|bytes| bytes.len() == 2);
+
+        match (reg, value) {
+            (Some(reg), Some(bytes)) => {
+                self.set_register(reg as usize, words_from_bytes(&bytes)[0]);
+                "OK".to_owned()
+            },
+            _ => error_reply()
+        }
+    }
+
+    fn register(&self, reg: usize) -> Word {
+        let cpu = &self.debugger.cpu;
+        match reg {
+            PC_REGISTER => cpu.pc as Word,
+            PSR_REGISTER => cpu.psr(),
+            _ => cpu.reg[reg]
+        }
+    }
+
+    fn set_register(&mut self, reg: usize, value: Word) {
+        let cpu = &mut self.debugger.cpu;
+        match reg {
+            PC_REGISTER => cpu.pc = value as Address,
+            PSR_REGISTER => cpu.set_psr(value),
+            _ => cpu.reg[reg] = value
+        }
+    }
+
+    // Reads don't go through the devices, so looking at the keyboard
+    // registers doesn't consume a key.
+    fn read_memory(&self, args: &str) -> String {
+        let (start, length) = match parse_range(args, MAX_TRANSFER_WORDS) {
+            Some(range) => range,
+            None => return error_reply()
+        };
+
+        (0..length)
+            .map(|offset| encode_word(self.debugger.cpu.mem.peek(start.wrapping_add(offset as Address))))
+            .collect()
+    }
+
+    // Writes do go through the devices, like the console's sm command.
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, ':');
+        let range = parts.next().and_then(|range| parse_range(range, MAX_TRANSFER_WORDS));
+        let bytes = parts.next().and_then(decode_bytes);
+
+        let (start, bytes) = match (range, bytes) {
+            (Some((start, length)), Some(bytes)) if bytes.len() == length * 2 => (start, bytes),
+            _ => return error_reply()
+        };
+
+        let cpu = &mut self.debugger.cpu;
+        for (offset, value) in words_from_bytes(&bytes).into_iter().enumerate() {
+            cpu.set_memory_address_value(start.wrapping_add(offset as Address), value);
+        }
+        "OK".to_owned()
+    }
+
+    // s [address]: optionally jump somewhere first, then run one instruction.
+    fn step(&mut self, args: &str) -> Failable<String> {
+        if !self.jump(args) { return Ok(error_reply()) }
+        if !self.debugger.cpu.running { return Ok(self.halted_reply()) }
+
+        Ok(match self.debugger.step() {
+            Ok(Some(reason)) => self.reason_reply(&reason),
+            Ok(None) if !self.debugger.cpu.running => self.halted_reply(),
+            Ok(None) => stop_reply(SIGTRAP),
+            Err(_) => stop_reply(SIGILL)
+        })
+    }
+
+    // c [address]: optionally jump somewhere first, then run until something
+    // stops the program or GDB interrupts it.
+    fn resume(&mut self, args: &str) -> Failable<String> {
+        if !self.jump(args) { return Ok(error_reply()) }
+        if !self.debugger.cpu.running { return Ok(self.halted_reply()) }
+
+        loop {
+            match self.debugger.continue_many(INSTRUCTIONS_PER_POLL) {
+                Ok(Some(reason)) => return Ok(self.reason_reply(&reason)),
+                Ok(None) => if self.interrupted()? { return Ok(stop_reply(SIGINT)) },
+                Err(_) => return Ok(stop_reply(SIGILL))
+            }
+        }
+    }
+
+    // Returns false if the address was malformed.
+    fn jump(&mut self, args: &str) -> bool {
+        if args.is_empty() { return true }
+
+        match parse_address(args) {
+            Some(addr) => {
+                self.debugger.cpu.pc = addr;
+                true
+            },
+            None => false
+        }
+    }
+
+    // bs and bc: step or continue backwards through the recorded history.
+    fn reverse(&mut self, args: &str) -> String {
+        match args {
+            "s" if self.debugger.step_back(1) == 1 => stop_reply(SIGTRAP),
+            "s" => self.reason_reply(&StopReason::StartOfHistory),
+            "c" => {
+                let reason = self.debugger.reverse_continue();
+                self.reason_reply(&reason)
+            },
+            _ => String::new()
+        }
+    }
+
+    // Z/z type,address,length: types 0 and 1 are breakpoints (there's no
+    // difference between software and hardware ones here) and 2, 3, and 4
+    // are write, read, and access watchpoints.
+    fn set_point(&mut self, args: &str, insert: bool) -> String {
+        let fields: Vec<&str> = args.split(',').collect();
+        if fields.len() < 3 { return error_reply() }
+
+        let (addr, length) = match (parse_address(fields[1]), parse_hex(fields[2])) {
+            (Some(addr), Some(length)) if length as usize <= MAX_WATCH_WORDS => (addr, length as usize),
+            _ => return error_reply()
+        };
+
+        let kind = match fields[0] {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return "OK".to_owned()
+            },
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new()
+        };
+
+        // anything past the end of memory is cut off rather than wrapped around
+        let words = ::std::cmp::max(1, length);
+        let end = ::std::cmp::min(addr as usize + words - 1, NUM_MEMORY_ADDRESSES as usize - 1);
+        let watchpoint = Watchpoint { start: addr, end: end as Address, kind };
+
+        if insert {
+            self.debugger.add_watchpoint(watchpoint);
+        } else if let Some(index) = self.debugger.watchpoints().iter().position(|&existing| existing == watchpoint) {
+            self.debugger.remove_watchpoint(index);
+        }
+        "OK".to_owned()
+    }
+
+    //////// STOP REPLIES ////////
+
+    fn reason_reply(&self, reason: &StopReason) -> String {
+        match *reason {
+            StopReason::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint { index, access, .. } => {
+                let kind = match self.debugger.watchpoints()[index].kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch"
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, access.address)
+            },
+            StopReason::Halted => self.halted_reply(),
+            StopReason::StartOfHistory => format!("T{:02x}replaylog:begin;", SIGTRAP),
+            StopReason::StepComplete => stop_reply(SIGTRAP)
+        }
+    }
+
+    // A halted machine is reported as an exited program, or as one killed by
+    // SIGILL if the operating system halted it because of an exception.
+    fn halted_reply(&self) -> String {
+        match self.debugger.cpu.last_exception {
+            Some(_) => format!("X{:02x}", SIGILL),
+            None => "W00".to_owned()
+        }
+    }
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn error_reply() -> String {
+    "E01".to_owned()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn parse_address(text: &str) -> Option<Address> {
+    parse_hex(text).filter(|&addr| addr <= 0xFFFF).map(|addr| addr as Address)
+}
+
+// address,length, where the length (in words) can be at most max_length
+fn parse_range(text: &str, max_length: usize) -> Option<(Address, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let start = parts.next().and_then(parse_address)?;
+    let length = parts.next().and_then(parse_hex).filter(|&length| length as usize <= max_length)?;
+    Some((start, length as usize))
+}
+
+// offset,length: the part of the target description qXfer asks for, prefixed
+// with m if there's more to come or l if that's the last of it.
+fn read_target_description(range: &str) -> String {
+    let mut parts = range.splitn(2, ',');
+    let (offset, length) = match (parts.next().and_then(parse_hex), parts.next().and_then(parse_hex)) {
+        (Some(offset), Some(length)) => (offset as usize, length as usize),
+        _ => return error_reply()
+    };
+
+    let description = TARGET_DESCRIPTION.as_bytes();
+    let start = ::std::cmp::min(offset, description.len());
+    let end = ::std::cmp::min(start.saturating_add(length), description.len());
+    let marker = if end < description.len() { "m" } else { "l" };
+    format!("{}{}", marker, String::from_utf8_lossy(&description[start..end]))
+}
+
+fn encode_word(value: Word) -> String {
+    let value = value as u16;
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn decode_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() { return None }
+
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn words_from_bytes(bytes: &[u8]) -> Vec<Word> {
+    bytes.chunks(2)
+        .map(|pair| (u16::from(pair[1]) << 8 | u16::from(pair[0])) as Word)
+        .collect()
+}
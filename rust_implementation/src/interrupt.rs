@@ -0,0 +1,63 @@
+//////////////////////////////////////////////////////
+// INTERRUPT CONTROLLER
+//////////////////////////////////////////////////////
+
+// Devices (or anything else driving the CPU) raise interrupts here; the CPU
+// checks between instructions for a request that outranks its current
+// priority level and, if there is one, vectors to its service routine.
+
+pub const NUM_PRIORITY_LEVELS: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterruptRequest {
+    pub vector: u8,
+    pub priority: u8
+}
+
+#[derive(Default)]
+pub struct InterruptController {
+    pending: Vec<InterruptRequest>
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        InterruptController {
+            pending: Vec::new()
+        }
+    }
+
+    // Raising a vector that is already pending just updates its priority.
+    pub fn raise(&mut self, vector: u8, priority: u8) {
+        let priority = priority.min(NUM_PRIORITY_LEVELS - 1);
+
+        match self.pending.iter_mut().find(|request| request.vector == vector) {
+            Some(request) => request.priority = priority,
+            None => self.pending.push(InterruptRequest { vector, priority })
+        }
+    }
+
+    // Withdraws a request that hasn't been serviced yet.
+    pub fn clear(&mut self, vector: u8) {
+        self.pending.retain(|request| request.vector != vector);
+    }
+
+    pub fn pending(&self) -> &[InterruptRequest] {
+        &self.pending
+    }
+
+    // The highest priority request that is strictly higher than the given
+    // priority level (the earliest raised wins a tie).
+    pub fn highest_above(&self, priority: u8) -> Option<InterruptRequest> {
+        self.pending.iter()
+            .filter(|request| request.priority > priority)
+            .fold(None, |best: Option<InterruptRequest>, request| match best {
+                Some(best) if best.priority >= request.priority => Some(best),
+                _ => Some(*request)
+            })
+    }
+
+    // Called once the CPU has started servicing the request.
+    pub fn acknowledge(&mut self, request: InterruptRequest) {
+        self.clear(request.vector);
+    }
+}
@@ -0,0 +1,56 @@
+// LC3 simulator library.  Everything needed to build, load, step, and inspect
+// an LC3 machine lives here so that other tools (graders, visualizers, the
+// console in main.rs) can all drive the same CPU.
+
+// Opcodes and the CPU keep their conventional LC3 spelling.
+#![allow(clippy::upper_case_acronyms)]
+
+#[macro_use] extern crate failure;
+extern crate num;
+extern crate num_traits;
+#[macro_use] extern crate serde_json;
+
+pub mod utils;
+pub mod program;
+pub mod errors;
+pub mod cpu;
+pub mod condition_code;
+pub mod privilege;
+pub mod interrupt;
+pub mod memory;
+pub mod console;
+pub mod devices;
+pub mod nice_vector;
+pub mod operation;
+pub mod assembler;
+pub mod disassembler;
+pub mod symbols;
+pub mod debug_map;
+pub mod loader;
+pub mod debugger;
+pub mod os;
+pub mod trace;
+pub mod gdb;
+pub mod dap;
+pub mod batch;
+pub mod commands;
+pub mod repl;
+
+pub use assembler::{assemble, Assembly, SourceMap};
+pub use condition_code::ConditionCode;
+pub use console::{BufferConsole, Console, FileConsole, StdConsole};
+pub use dap::DapServer;
+pub use debug_map::{DebugMap, SourceLocation};
+pub use debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+pub use disassembler::{disassemble, disassemble_with_symbols};
+pub use cpu::{Address, Instruction, TrapMode, Word, CPU, NUM_MEMORY_ADDRESSES, NUM_REGISTERS};
+pub use errors::{Failable, LC3Error};
+pub use gdb::{GdbStub, Transport};
+pub use privilege::Privilege;
+pub use loader::{load_program, load_program_file, LoadedProgram};
+pub use interrupt::{InterruptController, InterruptRequest};
+pub use memory::{Device, MemoryBus};
+pub use operation::Operation;
+pub use program::Program;
+pub use symbols::Symbols;
+pub use trace::{TraceEntry, TraceFormat, TraceWriter};
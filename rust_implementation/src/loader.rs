@@ -0,0 +1,48 @@
+use std::path::Path;
+use assembler;
+use debug_map::DebugMap;
+use errors::Failable;
+use program::Program;
+use symbols::Symbols;
+
+//////////////////////////////////////////////////////
+// LOADER
+//////////////////////////////////////////////////////
+
+// A program along with what a debugger can know about it: its labels and
+// where each of its words came from in its source.
+pub struct LoadedProgram {
+    pub program: Program,
+    pub symbols: Symbols,
+    pub debug_map: DebugMap
+}
+
+// Assembly source is assembled on the fly, which gives it both labels and a
+// debug map.  Anything else is a hex or obj program, which picks up the .sym
+// file (from lc3as) and the .dbg file (from asm) beside it if they're there.
+pub fn load_program_file<P: AsRef<Path>>(filename: P) -> Failable<LoadedProgram> {
+    let filename = filename.as_ref();
+
+    if filename.to_string_lossy().to_lowercase().ends_with(".asm") {
+        let assembly = assembler::assemble_file(filename)?;
+        return Ok(LoadedProgram {
+            program: assembly.program,
+            symbols: Symbols::from_table(&assembly.symbols),
+            debug_map: DebugMap::from_source_map(filename, &assembly.source_map)
+        })
+    }
+
+    let symbol_file = filename.with_extension("sym");
+    let debug_map_file = filename.with_extension("dbg");
+
+    Ok(LoadedProgram {
+        program: Program::from_file(filename)?,
+        symbols: if symbol_file.is_file() { Symbols::from_sym_file(symbol_file)? } else { Symbols::new() },
+        debug_map: if debug_map_file.is_file() { DebugMap::from_file(debug_map_file)? } else { DebugMap::new() }
+    })
+}
+
+// Just the program, for when its labels and source don't matter (like an OS image).
+pub fn load_program<P: AsRef<Path>>(filename: P) -> Failable<Program> {
+    Ok(load_program_file(filename)?.program)
+}
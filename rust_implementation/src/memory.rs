@@ -0,0 +1,144 @@
+use std::ops::Index;
+use std::ops::IndexMut;
+use console::Console;
+use cpu::{Address, Word, NUM_MEMORY_ADDRESSES};
+use devices::{Display, Keyboard, MachineControl};
+use errors::Failable;
+use interrupt::InterruptController;
+use nice_vector::Vector;
+use utils::Indexable;
+
+//////////////////////////////////////////////////////
+// MEMORY BUS
+//////////////////////////////////////////////////////
+
+// Routes memory accesses either to RAM or, for addresses that belong to a
+// device register, to the device that owns it.
+
+// Device registers live in the top page of memory (xFE00 - xFFFF).
+pub const DEVICE_PAGE_START: Address = 0xFE00;
+
+pub trait Device {
+    // Whether addr is one of this device's registers.
+    fn handles(&self, addr: Address) -> bool;
+
+    // Devices that do character I/O (the keyboard and display) go through the
+    // console.  Reads fail if the console can't give the device what it needs.
+    fn read(&mut self, addr: Address, console: &mut dyn Console) -> Failable<Word>;
+    fn write(&mut self, addr: Address, value: Word, console: &mut dyn Console);
+
+    // Reads a register without any side effects (for dumps and debuggers).
+    fn peek(&self, addr: Address) -> Word;
+
+    // Called between instructions so the device can raise or withdraw interrupts.
+    fn tick(&mut self, _interrupts: &mut InterruptController, _console: &mut dyn Console) {}
+
+    // The device's state before an instruction, so that stepping backwards
+    // can put it back.  Devices with nothing to put back keep the defaults.
+    fn save_state(&self) -> DeviceState {
+        Vec::new()
+    }
+
+    fn restore_state(&mut self, _state: &[usize]) {}
+}
+
+// Whatever a device needs to put itself back the way it was; only the device
+// that saved it knows what it means.
+pub type DeviceState = Vec<usize>;
+
+pub struct MemoryBus {
+    pub ram: Vector<Word>,
+    devices: Vec<Box<dyn Device>>
+}
+
+impl MemoryBus {
+    // Plain RAM with no devices attached.
+    pub fn new() -> MemoryBus {
+        MemoryBus {
+            ram: Vector::new(NUM_MEMORY_ADDRESSES),
+            devices: Vec::new()
+        }
+    }
+
+    // RAM plus the keyboard, display, and machine control registers.
+    pub fn with_standard_devices() -> MemoryBus {
+        let mut bus = MemoryBus::new();
+        bus.attach(Box::new(Keyboard::new()));
+        bus.attach(Box::new(Display::new()));
+        bus.attach(Box::new(MachineControl::new()));
+        bus
+    }
+
+    // Devices attached later take precedence over earlier ones for shared registers.
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.devices.insert(0, device);
+    }
+
+    pub fn handles(&self, addr: Address) -> bool {
+        self.devices.iter().any(|device| device.handles(addr))
+    }
+
+    pub fn read(&mut self, addr: Address, console: &mut dyn Console) -> Failable<Word> {
+        match self.device_for(addr) {
+            Some(index) => self.devices[index].read(addr, console),
+            None => Ok(self.ram[addr])
+        }
+    }
+
+    pub fn write(&mut self, addr: Address, value: Word, console: &mut dyn Console) {
+        match self.device_for(addr) {
+            Some(index) => self.devices[index].write(addr, value, console),
+            None => self.ram[addr] = value
+        }
+    }
+
+    pub fn peek(&self, addr: Address) -> Word {
+        match self.device_for(addr) {
+            Some(index) => self.devices[index].peek(addr),
+            None => self.ram[addr]
+        }
+    }
+
+    pub fn tick(&mut self, interrupts: &mut InterruptController, console: &mut dyn Console) {
+        for device in &mut self.devices {
+            device.tick(interrupts, console);
+        }
+    }
+
+    // Each device's state, in the order they're attached.
+    pub fn save_device_state(&self) -> Vec<DeviceState> {
+        self.devices.iter().map(|device| device.save_state()).collect()
+    }
+
+    pub fn restore_device_state(&mut self, state: &[DeviceState]) {
+        for (device, state) in self.devices.iter_mut().zip(state) {
+            device.restore_state(state);
+        }
+    }
+
+    fn device_for(&self, addr: Address) -> Option<usize> {
+        if addr < DEVICE_PAGE_START { return None }
+        self.devices.iter().position(|device| device.handles(addr))
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> MemoryBus {
+        MemoryBus::new()
+    }
+}
+
+// Indexing goes straight to RAM, bypassing devices (used for loading programs,
+// fetching instructions, and the like).
+impl<R: Indexable> Index<R> for MemoryBus {
+    type Output = Word;
+    fn index(&self, index: R) -> &Word {
+        &self.ram[index]
+    }
+}
+
+impl<R: Indexable> IndexMut<R> for MemoryBus {
+    fn index_mut(&mut self, index: R) -> &mut Word {
+        &mut self.ram[index]
+    }
+}
@@ -18,12 +18,12 @@ impl<T: Num + Clone> Vector<T> {
 impl<T: Num, R: Indexable> Index<R> for Vector<T> {
     type Output = T;
     fn index(&self, index: R) -> &T {
-        &self.vals[index.as_usize()]
+        &self.vals[index.to_usize()]
     }
 }
 
 impl<T: Num, R: Indexable> IndexMut<R> for Vector<T> {
     fn index_mut(&mut self, index: R) -> &mut T {
-        &mut self.vals[index.as_usize()]
+        &mut self.vals[index.to_usize()]
     }
 }
\ No newline at end of file
@@ -1,6 +1,5 @@
-use std;
-use cpu::CPU;
-use std::io::Read;
+use cpu::{Address, TrapMode, Word, CPU, ILLEGAL_OPCODE, PRIVILEGE_MODE_VIOLATION};
+use privilege::Privilege;
 use errors::Failable;
 use errors::LC3Error;
 use utils::{SignedBitSelection, UnsignedBitSelection};
@@ -38,35 +37,61 @@ impl Operation {
         match *self {
             Operation::BR => instr_br(cpu),
             Operation::ADD => instr_add(cpu),
-            Operation::LD => instr_ld(cpu),
+            Operation::LD => instr_ld(cpu)?,
             Operation::ST => instr_st(cpu),
             Operation::JSR => instr_jsr(cpu),
             Operation::AND => instr_and(cpu),
-            Operation::LDR => instr_ldr(cpu),
+            Operation::LDR => instr_ldr(cpu)?,
             Operation::STR => instr_str(cpu),
-            Operation::RTI => instr_rti()?,
+            Operation::RTI => instr_rti(cpu)?,
             Operation::NOT => instr_not(cpu),
-            Operation::LDI => instr_ldi(cpu),
-            Operation::STI => instr_sti(cpu),
+            Operation::LDI => instr_ldi(cpu)?,
+            Operation::STI => instr_sti(cpu)?,
             Operation::JMP => instr_jmp(cpu),
-            Operation::ERR => instr_err()?,
+            Operation::ERR => instr_err(cpu)?,
             Operation::LEA => instr_lea(cpu),
             Operation::TRAP => instr_trap(cpu)?
         }
 
         Ok(())
     }
+
+    // The registers the instruction in the CPU's IR writes, whatever values
+    // they end up with.  TRAP into an operating system, RTI and the exceptions
+    // raised in place of executing all push or pop the supervisor stack, so R6.
+    pub fn registers_written(&self, cpu: &CPU) -> Vec<usize> {
+        let dr = cpu.ir.bits(11, 9) as usize;
+
+        match *self {
+            Operation::ADD | Operation::AND | Operation::NOT | Operation::LEA |
+            Operation::LD | Operation::LDI | Operation::LDR => vec![dr],
+            Operation::JSR => vec![7],
+            Operation::TRAP if cpu.trap_mode == TrapMode::OperatingSystem => vec![6, 7],
+            Operation::TRAP => match cpu.ir.bits(7, 0) {
+                0x20 | 0x23 => vec![0], // GETC and IN
+                _ => vec![]
+            },
+            Operation::RTI | Operation::ERR => vec![6],
+            Operation::BR | Operation::ST | Operation::STR | Operation::STI | Operation::JMP => vec![]
+        }
+    }
 }
 /////////////////////////////////////////////////////
 // LC3 INSTRUCTION SET
 //////////////////////////////////////////////////////
 
+// Addresses are 16 bits wide, so offsets wrap around the ends of memory
+// (e.g. xFFFF + 1 = x0000) just like the real hardware.
+fn offset_address(base: Address, offset: i32) -> Address {
+    base.wrapping_add(offset as Address)
+}
+
 fn instr_br(cpu: &mut CPU) {
     let nzp = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
     if nzp & cpu.cc.bit() != 0 {
-        cpu.pc += pc_offset
+        cpu.pc = offset_address(cpu.pc, pc_offset)
     }
 }
 
@@ -77,42 +102,46 @@ fn instr_add(cpu: &mut CPU) {
 
     if encoding == 0 {
         let src2 = cpu.ir.bits(2, 0);
-        let result = cpu.reg[src1] + cpu.reg[src2];
+        let result = cpu.reg[src1].wrapping_add(cpu.reg[src2]);
         cpu.set_dr(dr, result);
     } else {
         let imm5 = cpu.ir.bits_signed(4, 0);
-        let result = cpu.reg[src1] + imm5;
+        let result = cpu.reg[src1].wrapping_add(imm5 as Word);
         cpu.set_dr(dr, result);
     }
 }
 
-fn instr_ld(cpu: &mut CPU) {
+fn instr_ld(cpu: &mut CPU) -> Failable<()> {
     let dr = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
-    let result = cpu.mem[cpu.pc + pc_offset];
+    let result = cpu.read_memory(offset_address(cpu.pc, pc_offset))?;
     cpu.set_dr(dr, result);
+    Ok(())
 }
 
 fn instr_st(cpu: &mut CPU) {
     let src = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
-    cpu.mem[(cpu.pc + pc_offset)] = cpu.reg[src];
+    let value = cpu.reg[src];
+    cpu.write_memory(offset_address(cpu.pc, pc_offset), value);
 }
 
 fn instr_jsr(cpu: &mut CPU) {
     let mode = cpu.ir.bits(11, 11);
 
-    cpu.reg[7] = cpu.pc;
-
-    if mode == 1 {
+    // the target is worked out before R7 changes so that JSRR R7 works
+    let target = if mode == 1 {
         let pc_offset = cpu.ir.bits_signed(10, 0);
-        cpu.pc = cpu.pc + pc_offset
-    } else { // JSSR
+        offset_address(cpu.pc, pc_offset)
+    } else { // JSRR
         let reg_num = cpu.ir.bits(8, 6);
-        cpu.pc = cpu.reg[reg_num];
-    }
+        cpu.reg[reg_num] as Address
+    };
+
+    cpu.reg[7] = cpu.pc as Word;
+    cpu.pc = target;
 }
 
 fn instr_and(cpu: &mut CPU) {
@@ -128,30 +157,46 @@ fn instr_and(cpu: &mut CPU) {
     } else {
         let imm5 = cpu.ir.bits_signed(4,0);
 
-        let result = cpu.reg[src1] & imm5;
+        let result = cpu.reg[src1] & imm5 as Word;
         cpu.set_dr(dr, result);
     }
 }
 
-fn instr_ldr(cpu: &mut CPU) {
+fn instr_ldr(cpu: &mut CPU) -> Failable<()> {
     let dr = cpu.ir.bits(11, 9);
     let base = cpu.ir.bits(8, 6);
     let offset = cpu.ir.bits_signed(5, 0);
 
-    let result = cpu.mem[cpu.reg[base] + offset];
+    let result = cpu.read_memory(offset_address(cpu.reg[base] as Address, offset))?;
     cpu.set_dr(dr, result);
+    Ok(())
 }
 
 fn instr_str(cpu: &mut CPU) {
-    let dr = cpu.ir.bits(11, 9);
+    let src = cpu.ir.bits(11, 9);
     let base = cpu.ir.bits(8, 6);
-    let offset = cpu.ir.bits(5, 0);
+    let offset = cpu.ir.bits_signed(5, 0);
 
-    cpu.mem[base + offset] = cpu.reg[dr];
+    let value = cpu.reg[src];
+    cpu.write_memory(offset_address(cpu.reg[base] as Address, offset), value);
 }
 
-fn instr_rti() -> Failable<()> {
-    Err(LC3Error::RTINotSupported)
+fn instr_rti(cpu: &mut CPU) -> Failable<()> {
+    if cpu.privilege == Privilege::User {
+        return cpu.raise_exception(PRIVILEGE_MODE_VIOLATION)
+    }
+
+    cpu.pc = cpu.pop_stack()? as Address;
+    let psr = cpu.pop_stack()?;
+    cpu.set_psr(psr);
+
+    // returning to user mode means switching back to the user stack
+    if cpu.privilege == Privilege::User {
+        cpu.saved_ssp = cpu.reg[6];
+        cpu.reg[6] = cpu.saved_usp;
+    }
+
+    Ok(())
 }
 
 fn instr_not(cpu: &mut CPU) {
@@ -162,28 +207,38 @@ fn instr_not(cpu: &mut CPU) {
     cpu.set_dr(dr, result);
 }
 
-fn instr_ldi(cpu: &mut CPU) {
+fn instr_ldi(cpu: &mut CPU) -> Failable<()> {
     let dr = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
-    let result = cpu.mem[cpu.mem[cpu.pc + pc_offset]];
+    let pointer = cpu.read_memory(offset_address(cpu.pc, pc_offset))?;
+    let result = cpu.read_memory(pointer as Address)?;
     cpu.set_dr(dr, result);
+    Ok(())
 }
 
-fn instr_sti(cpu: &mut CPU) {
+fn instr_sti(cpu: &mut CPU) -> Failable<()> {
     let src = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
-    let dest = cpu.mem[cpu.pc + pc_offset];
-    cpu.mem[dest] = cpu.reg[src];
+    let dest = cpu.read_memory(offset_address(cpu.pc, pc_offset))?;
+    let value = cpu.reg[src];
+    cpu.write_memory(dest as Address, value);
+    Ok(())
 }
 
 fn instr_jmp(cpu: &mut CPU) {
     let base = cpu.ir.bits(8, 6);
-    cpu.pc = cpu.reg[base];
+    cpu.pc = cpu.reg[base] as Address;
 }
 
-fn instr_err() -> Failable<()> {
+// The reserved opcode is an illegal opcode exception when an operating system
+// is around to handle it.
+fn instr_err(cpu: &mut CPU) -> Failable<()> {
+    if cpu.trap_mode == TrapMode::OperatingSystem {
+        return cpu.raise_exception(ILLEGAL_OPCODE)
+    }
+
     Err(LC3Error::UnusedOpCode)
 }
 
@@ -191,19 +246,24 @@ fn instr_lea(cpu: &mut CPU) {
     let dr = cpu.ir.bits(11, 9);
     let pc_offset = cpu.ir.bits_signed(8, 0);
 
-    let result = cpu.pc + pc_offset;
-    cpu.set_dr(dr, result);
+    let result = offset_address(cpu.pc, pc_offset);
+    cpu.set_dr(dr, result as Word);
 }
 
 fn instr_trap(cpu: &mut CPU) -> Failable<()> {
     let trap_code = cpu.ir.bits(7, 0);
 
+    if cpu.trap_mode == TrapMode::OperatingSystem {
+        return cpu.enter_trap_routine(trap_code as u8)
+    }
+
     match trap_code {
-        0x20 => trap_getchar(cpu),
-        0x21 => trap_out(cpu),
-        0x22 => trap_puts(cpu),
-        0x23 => trap_input(cpu),
-        0x25 => trap_halt(cpu),
+        0x20 => trap_getchar(cpu)?,
+        0x21 => trap_out(cpu)?,
+        0x22 => trap_puts(cpu)?,
+        0x23 => trap_input(cpu)?,
+        0x24 => trap_putsp(cpu)?,
+        0x25 => trap_halt(cpu)?,
         _ => return Err(LC3Error::UnsupportedTrapCode(trap_code))
     };
 
@@ -216,30 +276,67 @@ fn instr_trap(cpu: &mut CPU) -> Failable<()> {
 //////////////////////////////////////////////////////
 
 
-fn trap_getchar(cpu: &mut CPU) {
-    let input_char = std::io::stdin().bytes().next().ok_or(LC3Error::Unknown).unwrap().unwrap();
-    cpu.reg[0] = input_char as i32;
+fn trap_getchar(cpu: &mut CPU) -> Failable<()> {
+    let input_char = cpu.console.read_byte().ok_or(LC3Error::EndOfInput)?;
+    cpu.reg[0] = Word::from(input_char);
+    Ok(())
 }
 
-fn trap_out(cpu: &mut CPU) {
-    print!("{}", (cpu.reg[0] as u8) as char);
+fn trap_out(cpu: &mut CPU) -> Failable<()> {
+    let character = cpu.reg[0] as u8;
+    cpu.console.write_bytes(&[character])?;
+    Ok(())
 }
 
-fn trap_puts(cpu: &mut CPU) {
-    let mut temp_pc = cpu.reg[0] as u16;
+// One character per word (the low byte), up to a zero word.
+fn trap_puts(cpu: &mut CPU) -> Failable<()> {
+    let start = cpu.reg[0] as Address;
+    let mut temp_pc = start;
+    let mut characters = Vec::new();
 
     while cpu.mem[temp_pc] != 0 {
-        print!("{}", cpu.mem[temp_pc]);
-        temp_pc = temp_pc.wrapping_add(1);
+        characters.push(cpu.mem[temp_pc] as u8);
+        temp_pc = next_character_address(temp_pc, start)?;
     }
+
+    cpu.console.write_bytes(&characters)?;
+    Ok(())
+}
+
+// Two characters per word (low byte first), up to a zero byte.
+fn trap_putsp(cpu: &mut CPU) -> Failable<()> {
+    let start = cpu.reg[0] as Address;
+    let mut temp_pc = start;
+    let mut characters = Vec::new();
+
+    'words: loop {
+        let word = cpu.mem[temp_pc] as u16;
+        for &character in &[word as u8, (word >> 8) as u8] {
+            if character == 0 { break 'words }
+            characters.push(character);
+        }
+        temp_pc = next_character_address(temp_pc, start)?;
+    }
+
+    cpu.console.write_bytes(&characters)?;
+    Ok(())
 }
 
-fn trap_input(cpu: &mut CPU) {
-    print!("{}", "Enter a character: ");
+// Strings stop at the top of memory rather than wrapping around, so one
+// without a terminator can't be printed forever.
+fn next_character_address(addr: Address, start: Address) -> Failable<Address> {
+    addr.checked_add(1).ok_or(LC3Error::UnterminatedString(start))
+}
+
+fn trap_input(cpu: &mut CPU) -> Failable<()> {
+    cpu.console.write_bytes(b"Enter a character: ")?;
     trap_getchar(cpu)
 }
 
-fn trap_halt(cpu: &mut CPU) {
-    println!("{}", "Trap halt reached, halting CPU");
+// The message is the simulator's rather than the program's, so it stays out of
+// the program's output.
+fn trap_halt(cpu: &mut CPU) -> Failable<()> {
+    eprintln!("Trap halt reached, halting CPU");
     cpu.running = false;
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,18 @@
+use assembler::assemble;
+use program::Program;
+
+//////////////////////////////////////////////////////
+// BUNDLED OPERATING SYSTEM
+//////////////////////////////////////////////////////
+
+// The operating system every CPU boots with unless told otherwise: trap
+// routines for GETC, OUT, PUTS, IN, PUTSP and HALT that drive the device
+// registers, plus the exception handlers.  It is written in LC3 assembly so
+// it behaves exactly like an OS a student could write and load themselves.
+pub const SOURCE: &str = include_str!("../os/lc3os.asm");
+
+pub fn image() -> Program {
+    assemble(SOURCE)
+        .expect("the bundled operating system should always assemble")
+        .program
+}
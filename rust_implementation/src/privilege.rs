@@ -0,0 +1,27 @@
+use std::fmt;
+
+// Supervisor (operating system) or User (application) mode; stored in PSR[15].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Privilege { Supervisor, User }
+
+impl Privilege {
+    pub fn bit(&self) -> i32 {
+        match *self {
+            Privilege::Supervisor => 0,
+            Privilege::User => 1,
+        }
+    }
+
+    pub fn from_bit(bit: i32) -> Privilege {
+        if bit == 0 { Privilege::Supervisor } else { Privilege::User }
+    }
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Privilege::Supervisor => write!(f, "supervisor"),
+            Privilege::User => write!(f, "user"),
+        }
+    }
+}
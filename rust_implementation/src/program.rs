@@ -1,8 +1,10 @@
-use std;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 use errors::LC3Error;
 use cpu::{Address};
 use errors::Failable;
-use utils::lines_from_file;
 use cpu::Instruction;
 
 pub struct Program {
@@ -11,18 +13,53 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn from_file(filename: String) -> Failable<Program> {
-        let liness = lines_from_file(&filename)?;
-        let mut lines = liness.iter()
-            .map(|line| line.split_whitespace().next().unwrap());
+    pub fn new(origin: Address, instructions: Vec<Instruction>) -> Program {
+        Program {
+            header: origin,
+            instructions
+        }
+    }
+
+    // Loads either a hex text program or a binary .obj program.  The format is
+    // picked by extension when it is a known one and by the contents otherwise.
+    pub fn from_file<P: AsRef<Path>>(filename: P) -> Failable<Program> {
+        let bytes = fs::read(&filename)?;
+
+        match extension(filename.as_ref()).as_ref() {
+            "obj" => Program::from_obj_bytes(&bytes),
+            "hex" | "txt" => Program::from_hex_bytes(&bytes),
+            _ if looks_like_hex(&bytes) => Program::from_hex_bytes(&bytes),
+            _ => Program::from_obj_bytes(&bytes)
+        }
+    }
+
+    // Writes the program in the format matching the file's extension (hex by default).
+    pub fn write_file<P: AsRef<Path>>(&self, filename: P) -> Failable<()> {
+        if extension(filename.as_ref()) == "obj" {
+            self.write_obj_file(filename)
+        } else {
+            self.write_hex_file(filename)
+        }
+    }
+
+
+    //////////////////////////////////////////////////////
+    // HEX FORMAT
+    //////////////////////////////////////////////////////
+
+
+    // The origin on the first line followed by one instruction per line; anything
+    // after the first word on a line is ignored.
+    pub fn from_hex_string(contents: &str) -> Failable<Program> {
+        let mut lines = contents.lines()
+            .filter_map(|line| line.split_whitespace().next());
 
-        // .ok_or(Err(LC3Error::ProgramMissingHeader))??
-        let first_line = lines.next().unwrap();
-        let head = Instruction::from_str_radix(&first_line, 16).or(Err(LC3Error::BadProgramHeader))?;
+        let first_line = lines.next().ok_or(LC3Error::ProgramMissingHeader)?;
+        let head = Instruction::from_str_radix(first_line, 16).or(Err(LC3Error::BadProgramHeader))?;
 
-        let body = std::panic::catch_unwind(|| {
-            lines.map(|line| Instruction::from_str_radix(line, 16).unwrap()).collect()
-        }).or(Err(LC3Error::BadProgramInstructions))?;
+        let body = lines.map(|line| Instruction::from_str_radix(line, 16))
+            .collect::<Result<Vec<Instruction>, _>>()
+            .or(Err(LC3Error::BadProgramInstructions))?;
 
         Ok(Program {
             header: head,
@@ -30,6 +67,65 @@ impl Program {
         })
     }
 
+    // Writes the program in the same hex format that from_file reads.
+    pub fn write_hex_file<P: AsRef<Path>>(&self, filename: P) -> Failable<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(self.to_hex_string().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_hex_string(&self) -> String {
+        let mut hex = format!("{:04X}\n", self.header);
+        for instr in &self.instructions {
+            hex.push_str(&format!("{:04X}\n", instr));
+        }
+        hex
+    }
+
+    fn from_hex_bytes(bytes: &[u8]) -> Failable<Program> {
+        let contents = String::from_utf8_lossy(bytes);
+        Program::from_hex_string(&contents)
+    }
+
+
+    //////////////////////////////////////////////////////
+    // OBJ FORMAT
+    //////////////////////////////////////////////////////
+
+
+    // The binary format produced by lc3as and friends: big-endian 16-bit words,
+    // the first of which is the origin.
+    pub fn from_obj_bytes(bytes: &[u8]) -> Failable<Program> {
+        if bytes.len() < 2 { return Err(LC3Error::ObjectFileTruncated) }
+        if !bytes.len().is_multiple_of(2) { return Err(LC3Error::ObjectFileOddLength(bytes.len())) }
+
+        let mut words = bytes.chunks(2)
+            .map(|pair| (Instruction::from(pair[0]) << 8) | Instruction::from(pair[1]));
+
+        let head = words.next().ok_or(LC3Error::ObjectFileTruncated)?;
+
+        Ok(Program {
+            header: head,
+            instructions: words.collect()
+        })
+    }
+
+    pub fn write_obj_file<P: AsRef<Path>>(&self, filename: P) -> Failable<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.to_obj_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_obj_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 * (self.instructions.len() + 1));
+        for word in Some(&self.header).into_iter().chain(&self.instructions) {
+            bytes.push((word >> 8) as u8);
+            bytes.push(*word as u8);
+        }
+        bytes
+    }
+
+
     pub fn program_counter_start(&self) -> Address {
         self.header
     }
@@ -37,4 +133,21 @@ impl Program {
     pub fn instructions(&self) -> &Vec<Instruction> {
         &self.instructions
     }
+}
+
+fn extension(filename: &Path) -> String {
+    filename.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Hex programs are text whose lines each start with a hex word.
+fn looks_like_hex(bytes: &[u8]) -> bool {
+    match ::std::str::from_utf8(bytes) {
+        Ok(contents) => {
+            let mut words = contents.lines().filter_map(|line| line.split_whitespace().next()).peekable();
+            words.peek().is_some() && words.all(|word| Instruction::from_str_radix(word, 16).is_ok())
+        },
+        Err(_) => false
+    }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+use std::fs;
+use std::path::Path;
+use cpu::{self, AccessKind, Address, CPU};
+use debug_map::DebugMap;
+use debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+use disassembler::disassemble_with_symbols;
+use errors::{Failable, LC3Error};
+use loader::{load_program, load_program_file};
+use symbols::{describe_address, Symbols};
+use trace::TraceWriter;
+use utils::{read_console_line, parse_address, parse_code_address, parse_num, parse_source_line, parse_word};
+
+//////////////////////////////////////////////////////
+// INTERACTIVE CONSOLE
+//////////////////////////////////////////////////////
+
+// [--os image] [--native-traps] [--trace file] [--sym file] [--map file] program
+// -- the OS image is loaded over the bundled operating system (--native-traps
+// starts without one, handling TRAPs in the simulator), and the symbol file's
+// labels and the debug map's source lines are added to the program's own
+pub fn run(debugger: &mut Debugger, args: &[String]) -> Failable<()> {
+    let mut filename = None;
+    let mut os_image = None;
+    let mut symbol_files = Vec::new();
+    let mut debug_maps = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--os" => os_image = Some(args.next().ok_or(LC3Error::CommandMissingArguement)?),
+            "--native-traps" => debugger.cpu = CPU::without_operating_system(),
+            "--trace" => {
+                let trace = args.next().ok_or(LC3Error::CommandMissingArguement)?;
+                debugger.set_trace(Some(TraceWriter::create(trace)?))?;
+            },
+            "--sym" => symbol_files.push(args.next().ok_or(LC3Error::CommandMissingArguement)?),
+            "--map" => debug_maps.push(args.next().ok_or(LC3Error::CommandMissingArguement)?),
+            _ => filename = Some(arg)
+        }
+    }
+
+    if let Some(os_image) = os_image {
+        let image = load_program(os_image)?;
+        debugger.cpu.load_operating_system(&image);
+    }
+
+    let filename = filename.ok_or(LC3Error::MissingProgramFile)?;
+    let loaded = load_program_file(filename)?;
+    debugger.cpu.load_program(&loaded.program);
+    debugger.add_symbols(&loaded.symbols);
+    debugger.add_debug_map(&loaded.debug_map);
+
+    for symbol_file in symbol_files {
+        debugger.add_symbols(&Symbols::from_sym_file(symbol_file)?);
+    }
+    for debug_map in debug_maps {
+        debugger.add_debug_map(&DebugMap::from_file(debug_map)?);
+    }
+
+    println!("Beginning execution; type h for help");
+
+    loop {
+        // running out of input (like a script piped in without a q) quits too
+        let input = match read_console_line()? {
+            Some(input) if !should_quit(&input) => input,
+            _ => {
+                debugger.set_trace(None)?;
+                break
+            }
+        };
+
+        // a bad command or a faulting instruction shouldn't end the debugging session
+        if let Err(error) = execute_command(debugger, input) {
+            println!("Error: {reason}.", reason=error);
+        }
+    }
+
+    Ok(())
+}
+
+fn execute_command(debugger: &mut Debugger, input: String) -> Failable<()> {
+    // case 1: newline/whitespace => run a single instruction
+    if input.is_empty() {
+        if let Some(reason) = debugger.step()? {
+            print_stop_reason(debugger, &reason);
+        }
+        print_source_line(debugger);
+        return Ok(())
+    }
+
+    // case 2: command is a number => run that many instructions
+    if let Ok(num_cycles) = input.parse::<u32>() {
+        if let Some(reason) = debugger.step_many(num_cycles)? {
+            print_stop_reason(debugger, &reason);
+        }
+        print_source_line(debugger);
+        return Ok(())
+    }
+
+    // case 3: parse the command arguements and run the command.
+    let words : Vec<String> = input.split_whitespace().map(ToOwned::to_owned).collect();
+    let cmd = words.first().unwrap();
+    let symbols = debugger.symbols().clone();
+    let debug_map = debugger.debug_map().clone();
+    let cpu = &mut debugger.cpu;
+
+    match cmd.as_ref() {
+        "?" | "h" => {
+            print_help()
+        },
+        "d" => {
+            cpu.print_all_info(&symbols)
+        },
+        "sr" => {
+            // unsigned, so a negative register is rejected along with any other non-register
+            let register: usize = parse_num(&words, 1).or(Err(LC3Error::InvalidRegister))?;
+            if register >= cpu::NUM_REGISTERS as usize { return Err(LC3Error::InvalidRegister) }
+
+            let value = parse_word(&words, 2)?;
+            cpu.set_register_value(register as i32, value);
+        },
+        "sm" => {
+            let mem_addr = parse_address(&words, 1, &symbols)?;
+            let value = parse_word(&words, 2)?;
+            cpu.set_memory_address_value(mem_addr, value);
+        }
+        "g" => {
+            let addr = parse_code_address(&words, 1, &symbols, &debug_map)?;
+            cpu.set_pc_address(addr);
+        },
+        "b" | "break" => {
+            let addr = parse_code_address(&words, 1, &symbols, &debug_map)?;
+            let described = describe_code_address(addr, &symbols, &debug_map);
+            if debugger.add_breakpoint(addr) {
+                println!("Breakpoint set at {}", described);
+            } else {
+                println!("There is already a breakpoint at {}", described);
+            }
+        },
+        "bd" => {
+            let addr = parse_code_address(&words, 1, &symbols, &debug_map)?;
+            let described = describe_code_address(addr, &symbols, &debug_map);
+            if debugger.remove_breakpoint(addr) {
+                println!("Breakpoint at {} deleted", described);
+            } else {
+                println!("There is no breakpoint at {}", described);
+            }
+        },
+        "bl" => {
+            if debugger.breakpoints().is_empty() { println!("No breakpoints set") }
+            for &addr in debugger.breakpoints() {
+                println!("Breakpoint at {}", describe_code_address(addr, &symbols, &debug_map));
+            }
+        },
+        "wr" | "ww" | "wa" => {
+            let start = parse_address(&words, 1, &symbols)?;
+            let end = if words.len() > 2 { parse_address(&words, 2, &symbols)? } else { start };
+            if end < start { return Err(LC3Error::BadArguement) }
+
+            let kind = match cmd.as_ref() {
+                "wr" => WatchKind::Read,
+                "ww" => WatchKind::Write,
+                _ => WatchKind::Access
+            };
+
+            let watchpoint = Watchpoint { start, end, kind };
+            let index = debugger.add_watchpoint(watchpoint);
+            println!("Watchpoint {} set on {}", index, watchpoint);
+        },
+        "wd" => {
+            let index: usize = parse_num(&words, 1)?;
+            match debugger.remove_watchpoint(index) {
+                Some(watchpoint) => println!("Watchpoint {} on {} deleted", index, watchpoint),
+                None => println!("There is no watchpoint {}", index)
+            }
+        },
+        "wl" => {
+            if debugger.watchpoints().is_empty() { println!("No watchpoints set") }
+            for (index, watchpoint) in debugger.watchpoints().iter().enumerate() {
+                println!("Watchpoint {} on {}", index, watchpoint);
+            }
+        },
+        "i" => {
+            let vector = parse_num(&words, 1)?;
+            let priority = parse_num(&words, 2)?;
+            cpu.interrupts.raise(vector, priority);
+            println!("Interrupt x{:02X} raised at priority {}", vector, priority);
+        },
+        "c" => {
+            let reason = debugger.continue_execution()?;
+            print_stop_reason(debugger, &reason);
+            print_source_line(debugger);
+        },
+        "n" | "next" => {
+            let reason = debugger.step_over()?;
+            print_stop_reason(debugger, &reason);
+            print_source_line(debugger);
+        },
+        "finish" => {
+            let reason = debugger.step_out()?;
+            print_stop_reason(debugger, &reason);
+            print_source_line(debugger);
+        },
+        "sb" => {
+            let count = if words.len() > 1 { parse_num(&words, 1)? } else { 1 };
+            let undone = debugger.step_back(count);
+            println!("Stepped back {} instruction(s) to {}", undone, describe_address(debugger.cpu.pc, &symbols));
+            if undone < count { print_stop_reason(debugger, &StopReason::StartOfHistory) }
+            print_source_line(debugger);
+        },
+        "rc" => {
+            let reason = debugger.reverse_continue();
+            print_stop_reason(debugger, &reason);
+            print_source_line(debugger);
+        },
+        "list" => {
+            let around = if words.len() > 1 {
+                let (file, line) = parse_source_line(&words[1]).ok_or(LC3Error::BadArguement)?;
+                let (_, addr) = debug_map.resolve(file, line).ok_or_else(|| LC3Error::NoCodeAtLine(file.to_owned(), line))?;
+                (debug_map.location(addr).cloned(), line)
+            } else {
+                let location = debug_map.location(debugger.cpu.pc).cloned();
+                let line = location.as_ref().map_or(0, |location| location.line);
+                (location, line)
+            };
+
+            match around {
+                (Some(location), line) => list_source(debugger, &location.file, line)?,
+                (None, _) => println!("No source is known for {}", describe_address(debugger.cpu.pc, &symbols))
+            }
+        },
+        "t" => {
+            if words.len() > 1 {
+                debugger.set_trace(Some(TraceWriter::create(&words[1])?))?;
+                println!("Tracing executed instructions to {}", words[1]);
+            } else {
+                debugger.set_trace(None)?;
+                println!("Tracing stopped");
+            }
+        },
+        "sym" => {
+            if words.len() > 1 {
+                let loaded = Symbols::from_sym_file(&words[1])?;
+                debugger.add_symbols(&loaded);
+                println!("Loaded {} symbol(s) from {}", loaded.len(), words[1]);
+            } else {
+                if symbols.is_empty() { println!("No symbols loaded") }
+                for (label, &addr) in symbols.table() {
+                    println!("{:<20}x{:04X}", label, addr);
+                }
+            }
+        },
+        "hs" => {
+            if words.len() > 1 { debugger.set_history_size(parse_num(&words, 1)?) }
+            println!("Remembering up to {} instructions ({} recorded)", debugger.history_size(), debugger.history_len());
+        },
+        _ => {
+            return Err(LC3Error::UnrecognizedConsoleCommand(cmd.to_owned()))
+        }
+    }
+
+    Ok(())
+}
+
+fn print_stop_reason(debugger: &Debugger, reason: &StopReason) {
+    let symbols = debugger.symbols();
+    let pc = describe_address(debugger.cpu.pc, symbols);
+
+    match *reason {
+        StopReason::Breakpoint(addr) => println!("Stopped at breakpoint {}", describe_address(addr, symbols)),
+        StopReason::Watchpoint { index, access, pc, ir } => {
+            let action = match access.kind {
+                AccessKind::Read => "read",
+                AccessKind::Write => "wrote"
+            };
+            println!("Watchpoint {} ({}) hit by {}: {}; {} {} = x{:04X}",
+                     index, debugger.watchpoints()[index], describe_address(pc, symbols), disassemble_with_symbols(ir, pc, symbols),
+                     action, describe_address(access.address, symbols), access.value);
+        },
+        StopReason::Halted => println!("CPU halted at {}", pc),
+        StopReason::StartOfHistory => println!("Reached the oldest recorded instruction at {}", pc),
+        StopReason::StepComplete => println!("Stopped at {}", pc)
+    }
+}
+
+// How many lines either side of the current one list shows.
+const LIST_CONTEXT: usize = 5;
+
+// The source around a line, marking the PC's line with => and lines with a
+// breakpoint with *.
+fn list_source(debugger: &Debugger, file: &Path, around: usize) -> Failable<()> {
+    let source = fs::read_to_string(file)?;
+    let debug_map = debugger.debug_map();
+    let current = debug_map.location(debugger.cpu.pc);
+
+    let first = around.saturating_sub(LIST_CONTEXT).max(1);
+    for (index, text) in source.lines().enumerate().skip(first - 1).take(2 * LIST_CONTEXT + 1) {
+        let line = index + 1;
+        let on_line = |addr: Address| debug_map.location(addr).is_some_and(|location| location.file == file && location.line == line);
+
+        let marker = if current.is_some_and(|location| location.file == file && location.line == line) {
+            "=>"
+        } else if debugger.breakpoints().iter().any(|&addr| on_line(addr)) {
+            " *"
+        } else {
+            "  "
+        };
+        println!("{} {:>4}  {}", marker, line, text);
+    }
+
+    Ok(())
+}
+
+// Where the PC is in the source, along with that line: prog.asm:5: LOOP ADD R0, R0, #2.
+// Nothing is printed for code without any source, like the operating system's.
+fn print_source_line(debugger: &Debugger) {
+    if let Some(location) = debugger.debug_map().location(debugger.cpu.pc) {
+        let text = fs::read_to_string(&location.file).ok()
+            .and_then(|source| source.lines().nth(location.line.saturating_sub(1)).map(|text| text.trim().to_owned()))
+            .unwrap_or_default();
+        println!("{}: {}", location, text);
+    }
+}
+
+// xNNNN with its label, and its source line if it has one: x3002 (LOOP) on prog.asm:5
+fn describe_code_address(addr: Address, symbols: &Symbols, debug_map: &DebugMap) -> String {
+    match debug_map.location(addr) {
+        Some(location) => format!("{} on {}", describe_address(addr, symbols), location),
+        None => describe_address(addr, symbols)
+    }
+}
+
+// Only q on its own quits, so that labels like EQUAL can be typed.
+fn should_quit(console_input: &str) -> bool {
+    console_input == "q"
+}
+
+fn print_help() {
+    println!(r#"Simulator commands:
+        h or ? to print this message
+        q to quit
+        d to print (dump) the cpu info
+        g [address] to make the PC go to the the new address
+        b or break [address] to set a breakpoint at an address
+        bd [address] to delete the breakpoint at an address
+        list [file:line] to show the source around the PC (or a line)
+        bl to list all breakpoints
+        wr/ww/wa [address] [end address] to watch reads/writes/any access of an address (or range)
+        wd [number] to delete a watchpoint
+        wl to list all watchpoints
+        i [vector] [priority] to raise an interrupt
+        c to continue running until a breakpoint or watchpoint is hit or the cpu halts
+        n or next to run one instruction, running a whole subroutine or TRAP it calls
+        finish to run until the current subroutine returns
+        sb [count] to step back one (or count) instructions
+        rc to run backwards to the previous breakpoint
+        t [file] to trace executed instructions to a file (.jsonl for JSON Lines), or stop tracing
+        hs [size] to show (or set) how many instructions can be stepped back
+        sym [file] to load labels from a .sym file, or list the loaded ones
+        sm [address] [value] to set the value of a memory address
+        sr [reg_num] [value] to set the value of a register
+        *Press return to execute a single instruction cycle
+        *Enter an integer to execute that many instruction cycles
+        NOTE: Addresses and values must be in hex (xNNNN); addresses can also be
+              labels, optionally with an offset (LOOP, LOOP+2); g, b and bd
+              also take a source line (file.asm:42)"#)
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use assembler::{parse_number, SymbolTable};
+use cpu::Address;
+use errors::Failable;
+
+//////////////////////////////////////////////////////
+// SYMBOLS
+//////////////////////////////////////////////////////
+
+// Labels and the addresses they stand for, either from the assembler or from
+// a .sym file written by lc3as, so that addresses can be given and shown by
+// name.
+
+// How far past a label an address can be and still be described in terms of
+// it.  Anything further away is more likely some other part of memory than
+// part of whatever the label names.
+pub const MAX_LABEL_OFFSET: Address = 0x100;
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    by_name: SymbolTable,
+    by_address: BTreeMap<Address, String> // The first label (alphabetically) at each address
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn from_table(table: &SymbolTable) -> Symbols {
+        let mut symbols = Symbols::new();
+        for (label, &addr) in table {
+            symbols.insert(label, addr);
+        }
+        symbols
+    }
+
+    pub fn from_sym_file<P: AsRef<Path>>(filename: P) -> Failable<Symbols> {
+        Ok(Symbols::from_sym_string(&fs::read_to_string(filename)?))
+    }
+
+    // lc3as writes a commented table with a label and a hex address per row:
+    //
+    //   // Symbol table
+    //   // Scope level 0:
+    //   //	Symbol Name       Page Address
+    //   //	----------------  ------------
+    //   //	LOOP              3003
+    //
+    // Anything that isn't such a row is skipped.
+    pub fn from_sym_string(contents: &str) -> Symbols {
+        let mut symbols = Symbols::new();
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.trim_start_matches("//").split_whitespace().collect();
+            if fields.len() != 2 { continue }
+
+            if let Ok(addr) = Address::from_str_radix(fields[1], 16) {
+                symbols.insert(fields[0], addr);
+            }
+        }
+
+        symbols
+    }
+
+    pub fn insert(&mut self, label: &str, addr: Address) {
+        if let Some(old) = self.by_name.insert(label.to_owned(), addr) {
+            self.forget_address(label, old);
+        }
+
+        let keeps_existing = self.by_address.get(&addr).is_some_and(|existing| existing.as_str() < label);
+        if !keeps_existing {
+            self.by_address.insert(addr, label.to_owned());
+        }
+    }
+
+    pub fn extend(&mut self, other: &Symbols) {
+        for (label, &addr) in &other.by_name {
+            self.insert(label, addr);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    // Every label and its address, ordered by label.
+    pub fn table(&self) -> &SymbolTable {
+        &self.by_name
+    }
+
+    pub fn address(&self, label: &str) -> Option<Address> {
+        self.by_name.get(label).cloned()
+    }
+
+    // LABEL, LABEL+offset, or LABEL-offset, where the offset is any number the
+    // assembler accepts (+2, +#2, +x2).
+    pub fn parse_address(&self, text: &str) -> Option<Address> {
+        if let Some(addr) = self.address(text) { return Some(addr) }
+
+        let split = text.find(['+', '-'])?;
+        let (label, offset) = text.split_at(split);
+        let addr = self.address(label)?;
+
+        let offset = parse_number(offset.trim_start_matches('+'))?;
+        Some(addr.wrapping_add(offset as Address))
+    }
+
+    // The closest label at or before the address, as LABEL or LABEL+offset.
+    pub fn describe(&self, addr: Address) -> Option<String> {
+        let (&label_addr, label) = self.by_address.range(..=addr).next_back()?;
+
+        match addr - label_addr {
+            0 => Some(label.clone()),
+            offset if offset < MAX_LABEL_OFFSET => Some(format!("{}+{}", label, offset)),
+            _ => None
+        }
+    }
+
+    // A label that moved drops out of the reverse lookup, letting any other
+    // label at its old address stand in for it.
+    fn forget_address(&mut self, label: &str, addr: Address) {
+        if self.by_address.get(&addr).map(String::as_str) != Some(label) { return }
+        self.by_address.remove(&addr);
+
+        if let Some(other) = self.by_name.iter().find(|&(_, &other)| other == addr).map(|(other, _)| other.clone()) {
+            self.by_address.insert(addr, other);
+        }
+    }
+}
+
+// xNNNN, followed by the address's label if it has one: x3003 (LOOP+1)
+pub fn describe_address(addr: Address, symbols: &Symbols) -> String {
+    match symbols.describe(addr) {
+        Some(label) => format!("x{:04X} ({})", addr, label),
+        None => format!("x{:04X}", addr)
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use condition_code::ConditionCode;
+use cpu::{AccessKind, Address, Instruction, MemoryAccess, Word, CPU};
+use disassembler::disassemble;
+use serde_json::{Map, Value};
+use operation::Operation;
+use utils::UnsignedBitSelection;
+
+//////////////////////////////////////////////////////
+// EXECUTION TRACE
+//////////////////////////////////////////////////////
+
+// One executed instruction: where it was, what it was, and everything it
+// changed.  A cycle that entered an interrupt executed nothing; its entry has
+// the interrupt's vector instead, and the PC the service routine returns to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub pc: Address,
+    pub ir: Instruction,
+    pub interrupt: Option<u8>,
+    pub disassembly: String,
+    pub registers: Vec<(usize, Word)>, // Registers written, with their new values
+    pub accesses: Vec<MemoryAccess>,
+    pub cc: ConditionCode
+}
+
+impl TraceEntry {
+    // Builds the entry for the instruction cycle that started at pc.
+    pub fn capture(pc: Address, cpu: &CPU) -> TraceEntry {
+        let interrupt = cpu.interrupt_taken.map(|request| request.vector);
+
+        // written rather than changed, so ADD R1, R1, #0 still shows R1;
+        // entering an interrupt pushes onto the supervisor stack
+        let written = match interrupt {
+            Some(_) => vec![6],
+            None => Operation::from_code(cpu.ir.bits(15, 12) as u8)
+                .map(|operation| operation.registers_written(cpu))
+                .unwrap_or_default()
+        };
+        let registers = written.into_iter().map(|reg| (reg, cpu.reg[reg])).collect();
+
+        let disassembly = match interrupt {
+            Some(vector) => format!("INTERRUPT x{:02X}", vector),
+            None => disassemble(cpu.ir, pc)
+        };
+
+        TraceEntry {
+            pc,
+            ir: cpu.ir,
+            interrupt,
+            disassembly,
+            registers,
+            accesses: cpu.accesses.clone(),
+            cc: cpu.cc
+        }
+    }
+
+    // x3000  1261  ADD R1, R1, #1            R1=x0005  CC=P
+    // x3001  ----  INTERRUPT x80             R6=x2FFE  wrote ...
+    pub fn to_text(&self) -> String {
+        let ir = if self.interrupt.is_some() { "----".to_owned() } else { format!("{:04X}", self.ir) };
+        let mut line = format!("x{:04X}  {}  {:<24}", self.pc, ir, self.disassembly);
+
+        for &(reg, value) in &self.registers {
+            line.push_str(&format!("  R{}=x{:04X}", reg, value));
+        }
+
+        for access in &self.accesses {
+            match access.kind {
+                AccessKind::Read => line.push_str(&format!("  read x{:04X}=x{:04X}", access.address, access.value)),
+                AccessKind::Write => line.push_str(&format!("  wrote x{:04X}=x{:04X} (was x{:04X})",
+                                                            access.address, access.value, access.previous))
+            }
+        }
+
+        line.push_str(&format!("  CC={:?}", self.cc));
+        line
+    }
+
+    // A single JSON object; values are given as unsigned 16-bit numbers.
+    pub fn to_json(&self) -> String {
+        let registers: Map<String, Value> = self.registers.iter()
+            .map(|&(reg, value)| (format!("R{}", reg), json!(value as u16)))
+            .collect();
+
+        let reads: Vec<Value> = self.accesses.iter()
+            .filter(|access| access.kind == AccessKind::Read)
+            .map(|access| json!({ "address": access.address, "value": access.value as u16 }))
+            .collect();
+
+        let writes: Vec<Value> = self.accesses.iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| json!({ "address": access.address, "value": access.value as u16, "previous": access.previous as u16 }))
+            .collect();
+
+        let mut entry = json!({
+            "pc": self.pc,
+            "registers": registers,
+            "reads": reads,
+            "writes": writes,
+            "cc": format!("{:?}", self.cc)
+        });
+
+        // an interrupt's entry names its vector in place of the instruction
+        match self.interrupt {
+            Some(vector) => entry["interrupt"] = json!(vector),
+            None => {
+                entry["ir"] = json!(self.ir);
+                entry["asm"] = json!(self.disassembly);
+            }
+        }
+
+        entry.to_string()
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat { Text, JsonLines }
+
+impl TraceFormat {
+    // .jsonl and .json files get JSON Lines; anything else gets text.
+    pub fn for_file<P: AsRef<Path>>(filename: P) -> TraceFormat {
+        let extension = filename.as_ref().extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_ref() {
+            "jsonl" | "json" => TraceFormat::JsonLines,
+            _ => TraceFormat::Text
+        }
+    }
+}
+
+// Writes one line per traced instruction.
+pub struct TraceWriter {
+    out: Box<dyn Write>,
+    format: TraceFormat
+}
+
+impl TraceWriter {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> TraceWriter {
+        TraceWriter { out, format }
+    }
+
+    // The format is picked from the file's extension.
+    pub fn create<P: AsRef<Path>>(filename: P) -> io::Result<TraceWriter> {
+        let format = TraceFormat::for_file(&filename);
+        let file = BufWriter::new(File::create(filename)?);
+        Ok(TraceWriter::new(Box::new(file), format))
+    }
+
+    pub fn write_entry(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        let line = match self.format {
+            TraceFormat::Text => entry.to_text(),
+            TraceFormat::JsonLines => entry.to_json()
+        };
+        writeln!(self.out, "{}", line)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
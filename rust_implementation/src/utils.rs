@@ -2,17 +2,19 @@ use std;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use std::io;
-use std::io::BufRead;
 use errors::LC3Error;
 use std::str::FromStr;
 use errors::Failable;
+use cpu::{Address, Word};
+use symbols::Symbols;
+use debug_map::DebugMap;
+use console::read_stdin_line;
 use num_traits::PrimInt;
 
 //pub trait UsefulInteger: Integer + BitAnd<isize, Output = isize> + Not<Output = isize> + Add {}
 //impl<T> UsefulInteger for T where T: Integer + BitAnd<isize, Output = isize> + Not<Output = isize> + Add {}
 
-pub fn lines_from_file(filename: &AsRef<Path>) -> Result<Vec<String>, std::io::Error> {
+pub fn lines_from_file<P: AsRef<Path>>(filename: P) -> Result<Vec<String>, std::io::Error> {
     let mut file = File::open(filename)?;
 
     let mut contents = String::new();
@@ -21,13 +23,10 @@ pub fn lines_from_file(filename: &AsRef<Path>) -> Result<Vec<String>, std::io::E
     Ok(contents.lines().map(ToOwned::to_owned).collect())
 }
 
-pub fn read_console_line() -> Result<String, std::io::Error> {
-    let mut line = String::new();
-    let stdin = io::stdin();
-    stdin.lock().read_line(&mut line)?;
-    line.trim();
-
-    Ok(line)
+// None once stdin has run out.
+pub fn read_console_line() -> Result<Option<String>, std::io::Error> {
+    // through the console's reader, so it doesn't take input meant for the program
+    Ok(read_stdin_line().map(|line| line.trim().to_owned()))
 }
 
 // Unsigned Bit Selection
@@ -50,8 +49,51 @@ pub fn read_console_line() -> Result<String, std::io::Error> {
 // bits_s(3, 0, 0)  = 0000001.1. = 1
 
 
-pub fn parse_num<T: FromStr + PrimInt>(words: &Vec<String>, index: i32) -> Failable<T> {
+// Words may be given as signed decimal or as a raw 16-bit hex pattern (e.g. FFFF = -1).
+pub fn parse_word(words: &[String], index: i32) -> Failable<Word> {
+    parse_num::<Word>(words, index)
+        .or_else(|_| parse_num::<u16>(words, index).map(|num| num as Word))
+}
+
+// Addresses may also be given as a label, optionally with an offset (LOOP+2).
+// A label wins over bare hex spelled the same way (like ADD or BEEF).
+pub fn parse_address(words: &[String], index: i32, symbols: &Symbols) -> Failable<Address> {
+    let arg = words.get(index as usize).ok_or(LC3Error::CommandMissingArguement)?;
+    match symbols.parse_address(arg) {
+        Some(addr) => Ok(addr),
+        None => parse_num(words, index)
+    }
+}
+
+// Places in code may also be given as a source line (file.asm:42), which
+// stands for the first instruction at or after it.
+pub fn parse_code_address(words: &[String], index: i32, symbols: &Symbols, debug_map: &DebugMap) -> Failable<Address> {
+    let arg = words.get(index as usize).ok_or(LC3Error::CommandMissingArguement)?;
+    match parse_source_line(arg) {
+        Some((file, line)) => debug_map.resolve(file, line).map(|(_, addr)| addr)
+            .ok_or_else(|| LC3Error::NoCodeAtLine(file.to_owned(), line)),
+        None => parse_address(words, index, symbols)
+    }
+}
+
+// file.asm:42 => (file.asm, 42)
+pub fn parse_source_line(text: &str) -> Option<(&str, usize)> {
+    let (file, line) = text.rsplit_once(':')?;
+    if file.is_empty() { return None }
+    Some((file, line.parse().ok()?))
+}
+
+// Accepts xHEX, 0xHEX, #decimal, decimal, and (failing that) bare hex.
+pub fn parse_num<T: FromStr + PrimInt>(words: &[String], index: i32) -> Failable<T> {
     let arg = words.get(index as usize).ok_or(LC3Error::CommandMissingArguement)?;
+    if let Some(hex) = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X"))
+        .or_else(|| arg.strip_prefix('x')).or_else(|| arg.strip_prefix('X')) {
+        return T::from_str_radix(hex, 16).or(Err(LC3Error::BadArguement))
+    }
+    if let Some(decimal) = arg.strip_prefix('#') {
+        return decimal.parse::<T>().or(Err(LC3Error::BadArguement))
+    }
+
     let num =  arg.parse::<T>() // try to parse decimal
         .or(T::from_str_radix(arg, 16)) // try to parse hex
         .or(Err(LC3Error::BadArguement))?;
@@ -144,11 +186,12 @@ macro_rules! unsigned_bit_select_impl {
             fn bits(self, left: i32, right: i32) -> i32 {
                 assert!(left >= right, "left must be >= right");
 
+                // widen first so that selecting every bit of a word can't overflow the mask
                 let number_of_ones = (left - right) + 1;
-                let mask = ((1 << number_of_ones) - 1) << right;
-                let actual_value = (self & mask) >> right;
+                let mask = (1u128 << number_of_ones) - 1;
+                let actual_value = ((self as u128) >> right) & mask;
 
-                return actual_value as i32;
+                actual_value as i32
             }
         }
     )*)
@@ -160,17 +203,16 @@ macro_rules! signed_bit_select_impl {
             fn bits_signed(self, left: i32, right: i32) -> i32 {
                 assert!(left >= right, "left must be >= right");
 
-                let selection_is_positive = if left == right {
-                    true
-                } else {
-                    // selection is positive if leftmost bit is 0
-                    self & (1 << ((left - right) + 1)) == 0
-                };
+                let number_of_bits = (left - right) + 1;
+                let value = i64::from(self.bits(left, right));
+
+                // selection is positive if its leftmost bit is 0 (single bits are always positive)
+                let selection_is_positive = left == right || value & (1 << (number_of_bits - 1)) == 0;
 
                 if selection_is_positive {
-                    return self.bits(left, right) as i32;
+                    value as i32
                 } else {
-                    return -(((!self).bits(left - 1, right) + 1) as i32);
+                    (value - (1 << number_of_bits)) as i32
                 }
             }
         }
@@ -182,13 +224,13 @@ unsigned_bit_select_impl! { usize u8 u16 u32 u64 u128 isize i8 i16 i32 i64 i128
 signed_bit_select_impl! { usize u8 u16 u32 u64 u128 isize i8 i16 i32 i64 i128 }
 
 pub trait Indexable {
-    fn as_usize(self) -> usize;
+    fn to_usize(self) -> usize;
 }
 
 macro_rules! indexable_impl {
     ($($T:ty)*) => ($(
         impl Indexable for $T {
-            fn as_usize(self) -> usize {
+            fn to_usize(self) -> usize {
                 self as usize
             }
         }
@@ -0,0 +1,83 @@
+// The assembler's error reporting: each mistake is reported against the line
+// it's on, with a reason that says what's wrong.
+
+extern crate lc3;
+
+use lc3::{assemble, LC3Error};
+
+// The line and reason assembling the source failed with.
+fn error(source: &str) -> (usize, String) {
+    match assemble(source) {
+        Err(LC3Error::AssemblyError(line, reason)) => (line, reason),
+        Err(other) => panic!("expected an assembly error, got {}", other),
+        Ok(_) => panic!("expected {:?} not to assemble", source)
+    }
+}
+
+
+#[test]
+fn rejects_undefined_labels() {
+    let (line, reason) = error(".ORIG x3000\nAND R0, R0, #0\nBRnzp NOWHERE\n.END");
+    assert_eq!(line, 3);
+    assert_eq!(reason, "undefined label NOWHERE");
+
+    let (line, reason) = error(".ORIG x3000\nLEA R0, NOWHERE\n.END");
+    assert_eq!(line, 2);
+    assert_eq!(reason, "undefined label NOWHERE");
+
+    let (line, reason) = error(".ORIG x3000\n.FILL NOWHERE\n.END");
+    assert_eq!(line, 2);
+    assert_eq!(reason, "undefined label NOWHERE");
+}
+
+#[test]
+fn rejects_offsets_out_of_range() {
+    // LD's PCoffset9 reaches from -256 to +255 words past the next instruction
+    let far = ".ORIG x3000\nLD R0, FAR\n.BLKW #256\nFAR .FILL #1\n.END";
+    let (line, reason) = error(far);
+    assert_eq!(line, 2);
+    assert_eq!(reason, "offset to FAR 256 is out of range (-256 to 255)");
+
+    // one word nearer and it fits
+    assert!(assemble(".ORIG x3000\nLD R0, NEAR\n.BLKW #255\nNEAR .FILL #1\n.END").is_ok());
+
+    let (line, reason) = error(".ORIG x3000\nADD R0, R0, #16\n.END");
+    assert_eq!(line, 2);
+    assert_eq!(reason, "immediate value 16 is out of range (-16 to 15)");
+}
+
+#[test]
+fn rejects_labels_before_the_origin() {
+    let (line, reason) = error("START AND R0, R0, #0\n.ORIG x3000\n.END");
+    assert_eq!(line, 1);
+    assert_eq!(reason, "label START appears before .ORIG");
+
+    let (line, reason) = error("; nothing yet\nAND R0, R0, #0\n.END");
+    assert_eq!(line, 2);
+    assert_eq!(reason, "expected .ORIG before any other statement");
+
+    assert_eq!(error("; nothing at all\n").1, "missing .ORIG");
+}
+
+#[test]
+fn rejects_malformed_statements() {
+    let cases = [
+        (".ORIG x3000\nHERE ADD R0, R0, #1\nHERE HALT\n.END", 3, "duplicate label HERE"),
+        (".ORIG x3000\nADD R8, R0, #1\n.END", 2, "R8 is not a register (R0 - R7)"),
+        (".ORIG x3000\nADD R0, R0\n.END", 2, "expected 3 operand(s) but found 2"),
+        (".ORIG x3000\nHERE FROB R0\n.END", 2, "unknown instruction FROB"),
+        (".ORIG x3000\n.WORD 1\n.END", 2, "unknown directive .WORD"),
+        (".ORIG x3000\n.ORIG x4000\n.END", 2, "only one .ORIG is allowed per file"),
+        (".ORIG x3000\n.STRINGZ \"open\n.END", 2, "unterminated string"),
+    ];
+
+    for &(source, line, reason) in &cases {
+        assert_eq!(error(source), (line, reason.to_owned()), "{:?}", source);
+    }
+}
+
+#[test]
+fn reports_the_line_in_its_message() {
+    let error = assemble(".ORIG x3000\n\nBRnzp NOWHERE\n.END").err().unwrap();
+    assert_eq!(error.to_string(), "assembly failed on line 3: undefined label NOWHERE");
+}
@@ -0,0 +1,182 @@
+// Runs the lc3 binary's non-interactive run command the way a grader would,
+// checking what it prints and the exit code it finishes with.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const EXIT_HALTED: i32 = 0;
+const EXIT_BAD_USAGE: i32 = 1;
+const EXIT_STEP_LIMIT_EXCEEDED: i32 = 2;
+const EXIT_FAULTED: i32 = 3;
+
+// Echoes one character, read with GETC through the operating system.
+const ECHO: &str = r#"
+        .ORIG x3000
+        GETC
+        OUT
+        HALT
+        .END
+"#;
+
+const FOREVER: &str = r#"
+        .ORIG x3000
+LOOP    BRnzp LOOP
+        .END
+"#;
+
+// Opcode 1101 is reserved; the operating system's handler halts the machine.
+const ILLEGAL_OPCODE: &str = r#"
+        .ORIG x3000
+        .FILL xD000
+        HALT
+        .END
+"#;
+
+// Waits for a key without polling for it: the keyboard interrupt's service
+// routine stores the key, and the program echoes it once it's there.
+const KEYBOARD_INTERRUPT: &str = r#"
+        .ORIG x3000
+        LD R0, ISR
+        STI R0, VECTOR
+        LD R0, ENABLE
+        STI R0, KBSR
+WAIT    LD R0, KEY
+        BRz WAIT
+        OUT
+        HALT
+ISR     .FILL HANDLER
+VECTOR  .FILL x0180
+ENABLE  .FILL x4000
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+KEY     .BLKW 1
+HANDLER LDI R1, KBDR
+        ST R1, KEY
+        RTI
+        .END
+"#;
+
+// Writes a file for the test to hand to the simulator.
+fn test_file(test: &str, name: &str, contents: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("lc3-batch-test-{}-{}", std::process::id(), test));
+    fs::create_dir_all(&directory).unwrap();
+
+    let path = directory.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3")).arg("run").args(args).output().unwrap()
+}
+
+// Runs with stdin piped from the test rather than from an input file.
+fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3")).arg("run").args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+
+#[test]
+fn exits_cleanly_when_the_program_halts() {
+    let program = test_file("halted", "echo.asm", ECHO);
+    let input = test_file("halted", "input.txt", "k");
+
+    let output = run(&[path(&program), "--input", path(&input)]);
+    assert_eq!(output.status.code(), Some(EXIT_HALTED));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with('k'));
+}
+
+#[test]
+fn native_traps_leave_only_the_programs_output() {
+    let program = test_file("native-traps", "echo.asm", ECHO);
+    let input = test_file("native-traps", "input.txt", "k");
+
+    let output = run(&[path(&program), "--input", path(&input), "--native-traps"]);
+    assert_eq!(output.status.code(), Some(EXIT_HALTED));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "k");
+}
+
+#[test]
+fn stops_at_the_step_limit() {
+    let program = test_file("step-limit", "forever.asm", FOREVER);
+
+    let output = run(&[path(&program), "--max-steps", "1000"]);
+    assert_eq!(output.status.code(), Some(EXIT_STEP_LIMIT_EXCEEDED));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did not halt within 1000 instructions"));
+}
+
+#[test]
+fn bad_arguments_are_usage_errors() {
+    let program = test_file("bad-arguments", "echo.asm", ECHO);
+    let broken = test_file("bad-arguments", "broken.asm", ".ORIG x3000\nBRnzp NOWHERE\n.END\n");
+
+    for args in &[vec![], vec![path(&program), "--max-steps"], vec![path(&program), "--max-steps", "lots"],
+                  vec![path(&broken)]] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(EXIT_BAD_USAGE), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("Failed to load program"), "{:?}", args);
+    }
+}
+
+#[test]
+fn faults_when_the_program_raises_an_exception() {
+    let program = test_file("exception", "illegal.asm", ILLEGAL_OPCODE);
+
+    let output = run(&[path(&program), "--max-steps", "100000"]);
+    assert_eq!(output.status.code(), Some(EXIT_FAULTED));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("raised exception x01"));
+}
+
+#[test]
+fn faults_when_the_operating_system_reads_past_the_input() {
+    let program = test_file("end-of-input", "echo.asm", ECHO);
+    let input = test_file("end-of-input", "empty.txt", "");
+
+    // without a step limit the keyboard would otherwise be polled forever
+    let output = run(&[path(&program), "--input", path(&input)]);
+    assert_eq!(output.status.code(), Some(EXIT_FAULTED));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("there is none left"));
+
+    let output = run(&[path(&program), "--input", path(&input), "--max-steps", "100000"]);
+    assert_eq!(output.status.code(), Some(EXIT_FAULTED));
+}
+
+#[test]
+fn files_that_cant_be_opened_are_usage_errors() {
+    let program = test_file("missing-file", "echo.asm", ECHO);
+    let missing = std::env::temp_dir().join("lc3-batch-test-no-such-directory").join("input.txt");
+
+    let output = run(&[path(&program), "--input", path(&missing)]);
+    assert_eq!(output.status.code(), Some(EXIT_BAD_USAGE));
+}
+
+// /dev/full opens fine but refuses every write, so the trace fails once the program is running.
+#[cfg(target_os = "linux")]
+#[test]
+fn failing_to_write_while_running_is_a_fault() {
+    let program = test_file("write-failure", "echo.asm", ECHO);
+    let input = test_file("write-failure", "input.txt", "a");
+
+    let output = run(&[path(&program), "--input", path(&input), "--trace", "/dev/full"]);
+    assert_eq!(output.status.code(), Some(EXIT_FAULTED));
+}
+
+// The step limit only stops the test hanging if the key never arrives.
+#[test]
+fn keyboard_interrupts_see_keys_typed_on_stdin() {
+    let program = test_file("stdin-interrupt", "interrupt.asm", KEYBOARD_INTERRUPT);
+
+    let output = run_with_stdin(&[path(&program), "--max-steps", "10000000"], b"k");
+    assert_eq!(output.status.code(), Some(EXIT_HALTED), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with('k'));
+}
@@ -0,0 +1,54 @@
+// The worked examples from utils.rs, plus the edges of a word.
+
+extern crate lc3;
+
+use lc3::utils::{SignedBitSelection, UnsignedBitSelection};
+
+#[test]
+fn unsigned_selection() {
+    assert_eq!(59.bits(4, 0), 27);
+    assert_eq!(50.bits(5, 1), 25);
+    assert_eq!(10.bits(5, 1), 5);
+    assert_eq!(5.bits(5, 1), 2);
+    assert_eq!(1.bits(0, 0), 1);
+    assert_eq!(0.bits(0, 0), 0);
+}
+
+#[test]
+fn signed_selection() {
+    assert_eq!(50.bits_signed(4, 1), -7);
+    assert_eq!(50.bits_signed(5, 1), -7);
+    assert_eq!(59.bits_signed(4, 0), -5);
+    assert_eq!(10.bits_signed(5, 1), 5);
+    assert_eq!(5.bits_signed(5, 1), 2);
+    assert_eq!(1.bits_signed(0, 0), 1);
+    assert_eq!(0.bits_signed(0, 0), 0);
+    assert_eq!(3.bits_signed(0, 0), 1);
+}
+
+#[test]
+fn whole_word_selection() {
+    assert_eq!(0xFFFFu16.bits(15, 0), 0xFFFF);
+    assert_eq!(0xFFFFu16.bits_signed(15, 0), -1);
+    assert_eq!(0x8000u16.bits_signed(15, 0), -32768);
+    assert_eq!(0x7FFFu16.bits_signed(15, 0), 32767);
+    assert_eq!((-1i16).bits(15, 0), 0xFFFF);
+}
+
+#[test]
+fn instruction_fields() {
+    // ADD R1, R2, #-1
+    let instr = 0x12BFu16;
+    assert_eq!(instr.bits(15, 12), 0x1);
+    assert_eq!(instr.bits(11, 9), 1);
+    assert_eq!(instr.bits(8, 6), 2);
+    assert_eq!(instr.bits(5, 5), 1);
+    assert_eq!(instr.bits_signed(4, 0), -1);
+
+    // BRnzp #-256, the most negative 9-bit offset
+    assert_eq!(0x0F00u16.bits_signed(8, 0), -256);
+    assert_eq!(0x0EFFu16.bits_signed(8, 0), 255);
+
+    // JSR #-1024, the most negative 11-bit offset
+    assert_eq!(0x4C00u16.bits_signed(10, 0), -1024);
+}
@@ -0,0 +1,144 @@
+// Shared set up for the integration tests: a builder for putting a CPU into a
+// known state and a small wrapper for running it.
+
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use lc3::{assemble, Address, BufferConsole, ConditionCode, Instruction, Privilege, Word, CPU};
+
+pub const ORIGIN: Address = 0x3000;
+
+pub struct CpuBuilder {
+    cpu: CPU,
+    console: BufferConsole
+}
+
+impl CpuBuilder {
+    // Native traps, everything zeroed, and the PC at x3000.
+    pub fn new() -> CpuBuilder {
+        CpuBuilder::with_cpu(CPU::without_operating_system())
+    }
+
+    // Booted with the bundled operating system instead.
+    pub fn with_operating_system() -> CpuBuilder {
+        CpuBuilder::with_cpu(CPU::new())
+    }
+
+    fn with_cpu(mut cpu: CPU) -> CpuBuilder {
+        let console = BufferConsole::default();
+        cpu.console = Box::new(console.clone());
+        cpu.pc = ORIGIN;
+        CpuBuilder { cpu, console }
+    }
+
+    pub fn pc(mut self, pc: Address) -> CpuBuilder {
+        self.cpu.pc = pc;
+        self
+    }
+
+    pub fn reg(mut self, reg: usize, value: Word) -> CpuBuilder {
+        self.cpu.reg[reg] = value;
+        self
+    }
+
+    pub fn cc(mut self, cc: ConditionCode) -> CpuBuilder {
+        self.cpu.cc = cc;
+        self
+    }
+
+    pub fn privilege(mut self, privilege: Privilege) -> CpuBuilder {
+        self.cpu.privilege = privilege;
+        self
+    }
+
+    pub fn mem(mut self, addr: Address, value: Word) -> CpuBuilder {
+        self.cpu.mem[addr] = value;
+        self
+    }
+
+    // Puts a single instruction at the PC.
+    pub fn instruction(self, instr: Instruction) -> CpuBuilder {
+        let pc = self.cpu.pc;
+        self.mem(pc, instr as Word)
+    }
+
+    // Assembles the source and loads it, which also moves the PC to its origin.
+    pub fn program(mut self, source: &str) -> CpuBuilder {
+        let assembly = assemble(source).expect("test program should assemble");
+        self.cpu.load_program(&assembly.program);
+        self
+    }
+
+    pub fn input(self, input: &[u8]) -> CpuBuilder {
+        self.console.push_input(input);
+        self
+    }
+
+    pub fn build(self) -> Machine {
+        Machine { cpu: self.cpu, console: self.console }
+    }
+}
+
+pub struct Machine {
+    pub cpu: CPU,
+    pub console: BufferConsole
+}
+
+impl Machine {
+    pub fn step(&mut self) {
+        self.cpu.run_one_instruction_cycle().expect("instruction should execute");
+    }
+
+    // Runs until the CPU halts, failing the test if it takes too long.
+    pub fn run(&mut self) {
+        for _ in 0..100_000 {
+            if !self.cpu.running { return }
+            self.step();
+        }
+        panic!("the program didn't halt");
+    }
+
+    pub fn reg(&self, reg: usize) -> Word {
+        self.cpu.reg[reg]
+    }
+
+    pub fn mem(&self, addr: Address) -> Word {
+        self.cpu.mem[addr]
+    }
+
+    pub fn output(&self) -> String {
+        self.console.output_string()
+    }
+}
+
+// Shorthand for running one instruction from a given starting state.
+pub fn execute(instr: Instruction, builder: CpuBuilder) -> Machine {
+    let mut machine = builder.instruction(instr).build();
+    machine.step();
+    machine
+}
+
+
+// Somewhere to write a trace (or anything else) that the test can read back;
+// clones share the same buffer.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+// Drives the interactive console by typing commands into the lc3 binary.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R0, R0, #0      ; x3000
+        ADD R0, R0, #2      ; x3001
+        ST R0, RESULT       ; x3002
+        LD R1, RESULT       ; x3003
+        HALT                ; x3004
+RESULT  .BLKW 1             ; x3005
+        .END
+"#;
+
+fn source_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lc3-console-test-{}-{}.asm", std::process::id(), name));
+    fs::write(&path, PROGRAM).unwrap();
+    path
+}
+
+// Types each command (and a final q) and returns everything the console printed.
+fn session(name: &str, commands: &[&str]) -> String {
+    session_with_options(name, &[], commands)
+}
+
+fn session_with_options(name: &str, options: &[&str], commands: &[&str]) -> String {
+    let mut console = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .args(options)
+        .arg(source_file(name))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let typed: String = commands.iter().chain(&["q"]).map(|command| format!("{}\n", command)).collect();
+    console.stdin.take().unwrap().write_all(typed.as_bytes()).unwrap();
+
+    let output = console.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+
+#[test]
+fn rejects_registers_that_dont_exist() {
+    let output = session("registers", &["sr -1 5", "sr 8 5", "sr r1 5", "sr 7 5"]);
+
+    assert_eq!(output.matches("Error: invalid register; the choices are r0 - r7.").count(), 3);
+    assert!(output.contains("Successful simulation!"));
+}
+
+#[test]
+fn stops_at_breakpoints_until_they_are_deleted() {
+    let output = session("breakpoints", &["b x3002", "b RESULT-2", "bl", "c", "bd x3002", "bl", "bd x3002", "c"]);
+
+    assert!(output.contains("Breakpoint set at x3002"));
+    assert!(output.contains("Breakpoint set at x3003"));
+    assert!(output.contains("Stopped at breakpoint x3002"));
+    assert!(output.contains("Breakpoint at x3002") && output.contains("deleted"));
+    assert!(output.contains("There is no breakpoint at x3002"));
+    assert!(output.contains("Stopped at breakpoint x3003"));
+
+    // bl lists both, then just the one that's left
+    let listed = output.lines()
+        .filter(|line| line.starts_with("Breakpoint at x300") && !line.ends_with("deleted"))
+        .count();
+    assert_eq!(listed, 3);
+}
+
+#[test]
+fn rejects_breakpoints_at_nothing() {
+    let output = session("bad-breakpoints", &["b NOWHERE", "b", "bl"]);
+
+    assert_eq!(output.matches("Error:").count(), 2);
+    assert!(!output.contains("Breakpoint set"));
+    assert!(output.contains("No breakpoints set"));
+}
+
+#[test]
+fn stops_where_watched_memory_is_read_or_written() {
+    let output = session("watchpoints", &["ww RESULT", "wr x3005", "wa x3000 x3001", "wl", "c", "c", "wd 1", "wl", "wd 9", "c"]);
+
+    assert!(output.contains("Watchpoint 0 set on write x3005"));
+    assert!(output.contains("Watchpoint 1 set on read x3005"));
+    assert!(output.contains("Watchpoint 2 set on access x3000-x3001"));
+
+    // the instruction that did it is reported, not the one after it
+    assert!(output.contains("Watchpoint 0 (write x3005) hit by x3002: ST R0, x3005 (RESULT); wrote x3005 (RESULT) = x0002"));
+    assert!(output.contains("Watchpoint 1 (read x3005) hit by x3003: LD R1, x3005 (RESULT); read x3005 (RESULT) = x0002"));
+
+    assert!(output.contains("Watchpoint 1 on read x3005 deleted"));
+    assert!(output.contains("There is no watchpoint 9"));
+    assert!(output.contains("CPU halted"));
+}
+
+#[test]
+fn rejects_watchpoints_on_nothing() {
+    let output = session("bad-watchpoints", &["ww NOWHERE", "wr", "wa x3005 x3000", "wl"]);
+
+    assert_eq!(output.matches("Error:").count(), 3);
+    assert!(!output.contains("set on"));
+    assert!(output.contains("No watchpoints set"));
+}
+
+#[test]
+fn runs_without_the_operating_system_when_asked() {
+    let output = session_with_options("native-traps", &["--native-traps"], &["c"]);
+    assert!(output.contains("CPU halted"));
+    assert!(!output.contains("Halting the LC-3"));
+
+    // the bundled operating system prints a banner of its own when halting
+    let output = session("operating-system", &["c"]);
+    assert!(output.contains("Halting the LC-3"));
+}
+
+#[test]
+fn quits_when_its_input_runs_out() {
+    let mut console = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg(source_file("end-of-input"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // a step and then nothing more, without a q
+    console.stdin.take().unwrap().write_all(b"\n").unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while console.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            console.kill().unwrap();
+            panic!("the console kept running after its input ran out");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let output = console.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Successful simulation!"));
+}
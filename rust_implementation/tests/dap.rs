@@ -0,0 +1,396 @@
+// Drives the debug adapter with scripted requests, the same way an editor would.
+
+extern crate lc3;
+#[macro_use] extern crate serde_json;
+
+use std::fs;
+use std::io::{BufRead, Cursor, Read};
+use std::path::PathBuf;
+use serde_json::Value;
+use lc3::DapServer;
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R0, R0, #0      ; x3000
+        JSR DOUBLE          ; x3001
+        ADD R1, R0, #0      ; x3002
+        ; print a greeting
+        LEA R0, TEXT        ; x3003
+        PUTS                ; x3004
+        HALT                ; x3005
+DOUBLE  ADD R0, R0, #3      ; x3006
+        ADD R0, R0, R0      ; x3007
+        RET                 ; x3008
+TEXT    .STRINGZ "hi"
+        .END
+"#;
+
+const LOOP: &str = ".ORIG x3000\nBRnzp #-1\n.END";
+
+const ECHO: &str = ".ORIG x3000\nGETC\nOUT\nHALT\n.END";
+
+// Writes the source where the adapter can launch it from.
+fn source_file(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lc3-dap-test-{}-{}.asm", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+    fs::canonicalize(path).unwrap()
+}
+
+struct Script {
+    input: Vec<u8>,
+    seq: u64,
+    program: PathBuf
+}
+
+impl Script {
+    // Initializes the adapter and launches the source.
+    fn launch(name: &str, source: &str, stop_on_entry: bool) -> Script {
+        let mut script = Script { input: Vec::new(), seq: 0, program: source_file(name, source) };
+        script.send("initialize", json!({ "adapterID": "lc3" }));
+        let program = script.program.clone();
+        script.send("launch", json!({ "program": program, "stopOnEntry": stop_on_entry }));
+        script
+    }
+
+    // Returns the request's seq.
+    fn send(&mut self, command: &str, arguments: Value) -> u64 {
+        self.seq += 1;
+        let request = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments });
+        let content = request.to_string();
+        self.input.extend(format!("Content-Length: {}\r\n\r\n{}", content.len(), content).into_bytes());
+        self.seq
+    }
+
+    fn stack_trace(&mut self) -> u64 {
+        self.send("stackTrace", json!({ "threadId": 1 }))
+    }
+
+    fn set_breakpoints(&mut self, lines: &[u64]) -> u64 {
+        let breakpoints: Vec<Value> = lines.iter().map(|line| json!({ "line": line })).collect();
+        let program = self.program.clone();
+        self.send("setBreakpoints", json!({ "source": { "path": program }, "breakpoints": breakpoints }))
+    }
+
+    // Serves every request (ending with a disconnect) and returns everything the adapter sent.
+    fn run(mut self) -> Transcript {
+        self.send("disconnect", json!({}));
+
+        let mut output = Vec::new();
+        DapServer::new(Cursor::new(self.input), &mut output).serve().expect("the adapter should finish cleanly");
+        fs::remove_file(&self.program).unwrap();
+
+        let mut reader = Cursor::new(output);
+        let mut messages = Vec::new();
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap() == 0 { break }
+            let length: usize = header.trim().trim_start_matches("Content-Length: ").parse().unwrap();
+
+            let mut blank = String::new();
+            reader.read_line(&mut blank).unwrap();
+            assert_eq!(blank, "\r\n");
+
+            let mut content = vec![0; length];
+            reader.read_exact(&mut content).unwrap();
+            messages.push(serde_json::from_slice(&content).unwrap());
+        }
+
+        Transcript { messages }
+    }
+}
+
+struct Transcript {
+    messages: Vec<Value>
+}
+
+impl Transcript {
+    fn position(&self, seq: u64) -> usize {
+        self.messages.iter()
+            .position(|message| message["type"] == "response" && message["request_seq"] == seq)
+            .unwrap_or_else(|| panic!("request {} wasn't answered", seq))
+    }
+
+    fn response(&self, seq: u64) -> &Value {
+        &self.messages[self.position(seq)]
+    }
+
+    fn body(&self, seq: u64) -> &Value {
+        let response = self.response(seq);
+        assert_eq!(response["success"], true, "{}", response);
+        &response["body"]
+    }
+
+    // The events sent after the request's response and before the next response.
+    fn events_after(&self, seq: u64) -> Vec<&Value> {
+        self.messages[self.position(seq) + 1..].iter()
+            .take_while(|message| message["type"] == "event")
+            .collect()
+    }
+
+    fn stop_reason(&self, seq: u64) -> String {
+        let events = self.events_after(seq);
+        let stopped = events.iter().find(|event| event["event"] == "stopped")
+            .unwrap_or_else(|| panic!("request {} didn't stop the program: {:?}", seq, events));
+        stopped["body"]["reason"].as_str().unwrap().to_owned()
+    }
+
+    fn line(&self, stack_trace: u64) -> u64 {
+        self.body(stack_trace)["stackFrames"][0]["line"].as_u64().unwrap()
+    }
+
+    // name => value for every variable in the response.
+    fn variables(&self, seq: u64) -> Vec<(String, String)> {
+        self.body(seq)["variables"].as_array().unwrap().iter()
+            .map(|variable| (variable["name"].as_str().unwrap().to_owned(), variable["value"].as_str().unwrap().to_owned()))
+            .collect()
+    }
+}
+
+
+#[test]
+fn initializes_launches_and_stops_on_entry() {
+    let mut script = Script::launch("entry", PROGRAM, true);
+    let done = script.send("configurationDone", json!({}));
+    let trace = script.stack_trace();
+    let program = script.program.clone();
+    let transcript = script.run();
+
+    let capabilities = transcript.body(1);
+    assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+    assert_eq!(capabilities["supportsStepBack"], true);
+
+    // the adapter is ready for breakpoints once the program is launched
+    assert_eq!(transcript.events_after(2)[0]["event"], "initialized");
+    assert_eq!(transcript.stop_reason(done), "entry");
+
+    let frame = &transcript.body(trace)["stackFrames"][0];
+    assert_eq!(frame["line"], 3);
+    assert_eq!(frame["source"]["path"], json!(program));
+    assert_eq!(frame["instructionPointerReference"], "x3000");
+}
+
+#[test]
+fn maps_breakpoints_to_lines_with_code() {
+    let mut script = Script::launch("breakpoints", PROGRAM, false);
+    let set = script.set_breakpoints(&[4, 6, 40]);
+    let done = script.send("configurationDone", json!({}));
+    let first = script.stack_trace();
+    let resume = script.send("continue", json!({ "threadId": 1 }));
+    let second = script.stack_trace();
+    let transcript = script.run();
+
+    // the comment on line 6 moves down to the LEA; nothing is at or after line 40
+    let breakpoints = &transcript.body(set)["breakpoints"];
+    assert_eq!(breakpoints[0], json!({ "verified": true, "line": 4 }));
+    assert_eq!(breakpoints[1], json!({ "verified": true, "line": 7 }));
+    assert_eq!(breakpoints[2]["verified"], false);
+
+    assert_eq!(transcript.stop_reason(done), "breakpoint");
+    assert_eq!(transcript.line(first), 4);
+    assert_eq!(transcript.body(resume)["allThreadsContinued"], true);
+    assert_eq!(transcript.stop_reason(resume), "breakpoint");
+    assert_eq!(transcript.line(second), 7);
+}
+
+#[test]
+fn breakpoints_can_be_replaced() {
+    let mut script = Script::launch("replaced", PROGRAM, true);
+    script.set_breakpoints(&[4]);
+    script.set_breakpoints(&[11]);
+    script.send("configurationDone", json!({}));
+    let resume = script.send("continue", json!({ "threadId": 1 }));
+    let trace = script.stack_trace();
+    let transcript = script.run();
+
+    assert_eq!(transcript.stop_reason(resume), "breakpoint");
+    assert_eq!(transcript.line(trace), 11);
+}
+
+#[test]
+fn next_steps_over_subroutines_and_traps() {
+    let mut script = Script::launch("next", PROGRAM, true);
+    script.set_breakpoints(&[4]);
+    script.send("configurationDone", json!({}));
+    script.send("continue", json!({ "threadId": 1 }));
+
+    let over_jsr = script.send("next", json!({ "threadId": 1 }));
+    let after_jsr = script.stack_trace();
+    let registers = script.send("variables", json!({ "variablesReference": 1 }));
+
+    script.send("next", json!({ "threadId": 1 }));
+    script.send("next", json!({ "threadId": 1 }));
+    let over_puts = script.send("next", json!({ "threadId": 1 }));
+    let after_puts = script.stack_trace();
+    let transcript = script.run();
+
+    assert_eq!(transcript.stop_reason(over_jsr), "step");
+    assert_eq!(transcript.line(after_jsr), 5);
+    assert!(transcript.variables(registers).contains(&("R0".to_owned(), "x0006 (6)".to_owned())));
+
+    // PUTS goes through the operating system and prints before returning
+    let events = transcript.events_after(over_puts);
+    assert_eq!(events[0]["event"], "output");
+    assert_eq!(events[0]["body"]["output"], "hi");
+    assert_eq!(transcript.stop_reason(over_puts), "step");
+    assert_eq!(transcript.line(after_puts), 9);
+}
+
+#[test]
+fn steps_into_and_out_of_subroutines() {
+    let mut script = Script::launch("step-in", PROGRAM, true);
+    script.send("configurationDone", json!({}));
+    script.send("stepIn", json!({ "threadId": 1 }));
+    let into = script.send("stepIn", json!({ "threadId": 1 }));
+    let inside = script.stack_trace();
+    script.send("stepIn", json!({ "threadId": 1 }));
+    let out = script.send("stepOut", json!({ "threadId": 1 }));
+    let outside = script.stack_trace();
+    let transcript = script.run();
+
+    assert_eq!(transcript.stop_reason(into), "step");
+    assert_eq!(transcript.line(inside), 10);
+    assert_eq!(transcript.stop_reason(out), "step");
+    assert_eq!(transcript.line(outside), 5);
+}
+
+#[test]
+fn steps_back() {
+    let mut script = Script::launch("step-back", PROGRAM, true);
+    script.send("configurationDone", json!({}));
+    script.send("stepIn", json!({ "threadId": 1 }));
+    script.send("stepIn", json!({ "threadId": 1 }));
+    let back = script.send("stepBack", json!({ "threadId": 1 }));
+    let trace = script.stack_trace();
+    script.send("stepBack", json!({ "threadId": 1 }));
+    let oldest = script.send("stepBack", json!({ "threadId": 1 }));
+    let transcript = script.run();
+
+    assert_eq!(transcript.stop_reason(back), "step");
+    assert_eq!(transcript.line(trace), 4);
+
+    let stopped = &transcript.events_after(oldest)[0]["body"];
+    assert_eq!(stopped["text"], "Reached the oldest recorded instruction");
+}
+
+#[test]
+fn shows_registers_and_memory() {
+    let mut script = Script::launch("variables", PROGRAM, true);
+    script.send("configurationDone", json!({}));
+    script.send("stepIn", json!({ "threadId": 1 }));
+    let scopes = script.send("scopes", json!({ "frameId": 0 }));
+    let registers = script.send("variables", json!({ "variablesReference": 1 }));
+    let psr = script.send("variables", json!({ "variablesReference": 3 }));
+    let memory = script.send("variables", json!({ "variablesReference": 2 }));
+    let near_pc = script.send("variables", json!({ "variablesReference": 4 }));
+    let devices = script.send("variables", json!({ "variablesReference": 6 }));
+    let transcript = script.run();
+
+    let names: Vec<&Value> = transcript.body(scopes)["scopes"].as_array().unwrap().iter().map(|scope| &scope["name"]).collect();
+    assert_eq!(names, vec!["Registers", "Memory"]);
+
+    let registers = transcript.variables(registers);
+    assert_eq!(registers.len(), 12);
+    assert!(registers.contains(&("PC".to_owned(), "x3001".to_owned())));
+    assert!(registers.contains(&("IR".to_owned(), "x5020".to_owned())));
+    assert!(registers.contains(&("CC".to_owned(), "Z".to_owned())));
+    assert!(registers.contains(&("PSR".to_owned(), "x8002".to_owned())));
+
+    assert_eq!(transcript.variables(psr)[0], ("Privilege".to_owned(), "user".to_owned()));
+
+    let regions = transcript.variables(memory);
+    assert_eq!(regions[0], ("Near PC".to_owned(), "x2FFD - x3008".to_owned()));
+    assert_eq!(regions[1].0, "Stack (R6)");
+
+    let near_pc = transcript.variables(near_pc);
+    assert_eq!(near_pc[4], ("x3001".to_owned(), "x4804  JSR x3006  <- PC".to_owned()));
+
+    let devices = transcript.variables(devices);
+    assert_eq!(devices.iter().map(|device| device.0.as_ref()).collect::<Vec<&str>>(), vec!["KBSR", "KBDR", "DSR", "DDR", "MCR"]);
+}
+
+#[test]
+fn runs_to_the_end() {
+    let mut script = Script::launch("exit", PROGRAM, false);
+    let done = script.send("configurationDone", json!({}));
+    let transcript = script.run();
+
+    let events = transcript.events_after(done);
+    let names: Vec<&Value> = events.iter().map(|event| &event["event"]).collect();
+    assert_eq!(names, vec!["output", "exited", "terminated"]);
+    assert!(events[0]["body"]["output"].as_str().unwrap().starts_with("hi"));
+    assert_eq!(events[1]["body"]["exitCode"], 0);
+}
+
+#[test]
+fn pauses_a_running_program() {
+    let mut script = Script::launch("pause", LOOP, false);
+    let done = script.send("configurationDone", json!({}));
+    let pause = script.send("pause", json!({ "threadId": 1 }));
+    let trace = script.stack_trace();
+    let transcript = script.run();
+
+    // the pause is answered while the program runs, before it stops
+    assert!(transcript.position(pause) < transcript.position(trace));
+    assert_eq!(transcript.stop_reason(pause), "pause");
+    assert_eq!(transcript.events_after(done).len(), 0);
+    assert_eq!(transcript.line(trace), 2);
+}
+
+#[test]
+fn pausing_a_stopped_program_only_answers() {
+    let mut script = Script::launch("pause-stopped", PROGRAM, true);
+    script.send("configurationDone", json!({}));
+    let pause = script.send("pause", json!({ "threadId": 1 }));
+    let transcript = script.run();
+
+    assert_eq!(transcript.response(pause)["success"], true);
+    assert_eq!(transcript.events_after(pause).len(), 0);
+}
+
+#[test]
+fn types_what_the_debug_console_evaluates() {
+    // before the program runs
+    let mut script = Script::launch("typed-early", ECHO, true);
+    script.send("configurationDone", json!({}));
+    let typed = script.send("evaluate", json!({ "expression": "k", "context": "repl" }));
+    let run = script.send("continue", json!({ "threadId": 1 }));
+    let transcript = script.run();
+
+    assert_eq!(transcript.body(typed)["result"], "");
+    let events = transcript.events_after(run);
+    assert!(events[0]["body"]["output"].as_str().unwrap().starts_with('k'), "{:?}", events);
+
+    // and while it waits for a key
+    let mut script = Script::launch("typed-late", ECHO, false);
+    let done = script.send("configurationDone", json!({}));
+    let typed = script.send("evaluate", json!({ "expression": "z" }));
+    let transcript = script.run();
+
+    assert_eq!(transcript.events_after(done).len(), 0);
+    let events = transcript.events_after(typed);
+    assert!(events[0]["body"]["output"].as_str().unwrap().starts_with('z'), "{:?}", events);
+    assert_eq!(events.last().unwrap()["event"], "terminated");
+}
+
+#[test]
+fn only_the_debug_console_evaluates() {
+    let mut script = Script::launch("watch", PROGRAM, true);
+    script.send("configurationDone", json!({}));
+    let watch = script.send("evaluate", json!({ "expression": "R0", "context": "watch" }));
+    let transcript = script.run();
+
+    assert_eq!(transcript.response(watch)["success"], false);
+}
+
+#[test]
+fn fails_requests_without_a_program() {
+    let mut script = Script { input: Vec::new(), seq: 0, program: source_file("unlaunched", LOOP) };
+    script.send("initialize", json!({}));
+    let trace = script.stack_trace();
+    let unknown = script.send("frobnicate", json!({}));
+    let transcript = script.run();
+
+    assert_eq!(transcript.response(trace)["success"], false);
+    assert_eq!(transcript.response(trace)["message"], "no program has been launched");
+    assert_eq!(transcript.response(unknown)["success"], false);
+}
@@ -0,0 +1,140 @@
+// Debug maps from the assembler, written beside programs and read back by the
+// loader, and source lines given in place of addresses.
+
+extern crate lc3;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use lc3::{assemble, load_program_file, DebugMap, LC3Error, Symbols};
+use lc3::utils::parse_code_address;
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R1, R0, #3
+
+LOOP    ADD R0, R0, #2
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+        .END
+"#;
+
+// A directory of its own for each test, so they can run side by side.
+fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("lc3-debug-map-test-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&directory).unwrap();
+    fs::canonicalize(directory).unwrap()
+}
+
+fn source_file(directory: &Path) -> PathBuf {
+    let path = directory.join("loop.asm");
+    fs::write(&path, PROGRAM).unwrap();
+    path
+}
+
+fn args(text: &str) -> Vec<String> {
+    text.split_whitespace().map(ToOwned::to_owned).collect()
+}
+
+
+#[test]
+fn maps_each_word_to_its_source_line() {
+    let directory = test_directory("lines");
+    let source = source_file(&directory);
+    let debug_map = DebugMap::from_source_map(&source, &assemble(PROGRAM).unwrap().source_map);
+
+    let location = debug_map.location(0x3002).unwrap();
+    assert_eq!(location.file, source);
+    assert_eq!(location.line, 6);
+    assert_eq!(location.to_string(), "loop.asm:6");
+
+    assert_eq!(debug_map.location(0x3005).unwrap().line, 9);
+    assert!(debug_map.location(0x3006).is_none());
+}
+
+#[test]
+fn writes_and_reads_maps_relative_to_their_directory() {
+    let directory = test_directory("round-trip");
+    let source = source_file(&directory);
+    let debug_map = DebugMap::from_source_map(&source, &assemble(PROGRAM).unwrap().source_map);
+
+    let map_file = directory.join("loop.dbg");
+    debug_map.write_file(&map_file).unwrap();
+
+    let contents = fs::read_to_string(&map_file).unwrap();
+    assert!(contents.starts_with("// LC3 debug map\n"));
+    assert!(contents.contains("\nx3000 loop.asm:3\n"));
+
+    let read = DebugMap::from_file(&map_file).unwrap();
+    for addr in 0x3000..0x3006 {
+        assert_eq!(read.location(addr), debug_map.location(addr));
+    }
+}
+
+#[test]
+fn loads_the_debug_map_beside_a_program() {
+    let directory = test_directory("loader");
+    let source = source_file(&directory);
+    let assembly = assemble(PROGRAM).unwrap();
+
+    let hex_file = directory.join("loop.hex");
+    assembly.program.write_file(&hex_file).unwrap();
+    DebugMap::from_source_map(&source, &assembly.source_map).write_file(directory.join("loop.dbg")).unwrap();
+
+    let loaded = load_program_file(&hex_file).unwrap();
+    assert_eq!(loaded.debug_map.location(0x3004).unwrap().line, 8);
+    assert_eq!(loaded.debug_map.location(0x3004).unwrap().file, source);
+
+    // assembly source is mapped as it's assembled
+    let loaded = load_program_file(&source).unwrap();
+    assert_eq!(loaded.debug_map.location(0x3004).unwrap().line, 8);
+    assert_eq!(loaded.symbols.address("LOOP"), Some(0x3002));
+
+    // and a program with no map beside it has none
+    fs::remove_file(directory.join("loop.dbg")).unwrap();
+    assert!(load_program_file(&hex_file).unwrap().debug_map.is_empty());
+}
+
+#[test]
+fn resolves_lines_to_the_next_one_with_code() {
+    let directory = test_directory("resolve");
+    let source = source_file(&directory);
+    let debug_map = DebugMap::from_source_map(&source, &assemble(PROGRAM).unwrap().source_map);
+
+    assert_eq!(debug_map.resolve("loop.asm", 6), Some((6, 0x3002)));
+    assert_eq!(debug_map.resolve("loop.asm", 5), Some((6, 0x3002))); // blank line
+    assert_eq!(debug_map.resolve(&source.to_string_lossy(), 3), Some((3, 0x3000)));
+    assert_eq!(debug_map.resolve("loop.asm", 10), None);
+    assert_eq!(debug_map.resolve("other.asm", 6), None);
+}
+
+#[test]
+fn parses_source_lines_as_code_addresses() {
+    let directory = test_directory("parse");
+    let source = source_file(&directory);
+    let assembly = assemble(PROGRAM).unwrap();
+    let debug_map = DebugMap::from_source_map(&source, &assembly.source_map);
+    let symbols = Symbols::from_table(&assembly.symbols);
+
+    assert_eq!(parse_code_address(&args("b loop.asm:7"), 1, &symbols, &debug_map).unwrap(), 0x3003);
+    assert_eq!(parse_code_address(&args("b LOOP+1"), 1, &symbols, &debug_map).unwrap(), 0x3003);
+    assert_eq!(parse_code_address(&args("b x3003"), 1, &symbols, &debug_map).unwrap(), 0x3003);
+
+    match parse_code_address(&args("b loop.asm:40"), 1, &symbols, &debug_map) {
+        Err(LC3Error::NoCodeAtLine(file, line)) => assert_eq!((file.as_str(), line), ("loop.asm", 40)),
+        other => panic!("expected no code at loop.asm:40, got {:?}", other)
+    }
+}
+
+#[test]
+fn rejects_malformed_maps() {
+    assert!(DebugMap::from_string("// LC3 debug map\n\nx3000 loop.asm:3\n", Path::new("")).is_ok());
+
+    for contents in &["x3000 loop.asm", "x3000", "nowhere loop.asm:3", "x3000 loop.asm:three"] {
+        match DebugMap::from_string(contents, Path::new("")) {
+            Err(LC3Error::BadDebugMap(1)) => (),
+            other => panic!("expected {:?} to be rejected, got {:?}", contents, other.map(|_| ()))
+        }
+    }
+}
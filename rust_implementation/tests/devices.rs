@@ -0,0 +1,181 @@
+// The memory-mapped device registers as programs see them: polling the
+// keyboard and display, and stopping the clock through the MCR.
+
+extern crate lc3;
+
+use lc3::{assemble, BufferConsole, FileConsole, InterruptController, LC3Error, CPU};
+use lc3::devices::{DDR, DSR, KBDR, KBSR, KEYBOARD_INTERRUPT_VECTOR, MCR};
+
+// Echoes two keys by polling, the way the operating system's GETC and OUT do.
+const POLLING_ECHO: &str = r#"
+        .ORIG x3000
+        AND R2, R2, #0
+        ADD R2, R2, #2
+KEY     LDI R1, KBSRP
+        BRzp KEY
+        LDI R0, KBDRP
+SHOW    LDI R1, DSRP
+        BRzp SHOW
+        STI R0, DDRP
+        ADD R2, R2, #-1
+        BRp KEY
+        HALT
+KBSRP   .FILL xFE00
+KBDRP   .FILL xFE02
+DSRP    .FILL xFE04
+DDRP    .FILL xFE06
+        .END
+"#;
+
+// A CPU reading its input from, and writing its output to, the returned console.
+fn machine(input: &[u8]) -> (CPU, BufferConsole) {
+    let console = BufferConsole::new(input);
+    let mut cpu = CPU::without_operating_system();
+    cpu.console = Box::new(console.clone());
+    (cpu, console)
+}
+
+// Runs the CPU until it halts, failing the test if it takes too long.
+fn run(cpu: &mut CPU) {
+    for _ in 0..10_000 {
+        if !cpu.running { return }
+        cpu.run_one_instruction_cycle().unwrap();
+    }
+    panic!("the program didn't halt");
+}
+
+
+#[test]
+fn polls_the_keyboard_and_display() {
+    let (mut cpu, console) = machine(b"ok");
+    cpu.load_program(&assemble(POLLING_ECHO).unwrap().program);
+    run(&mut cpu);
+
+    assert!(console.output_string().starts_with("ok"), "{}", console.output_string());
+    assert_eq!(cpu.reg[0], 'k' as i16);
+}
+
+#[test]
+fn the_keyboard_is_ready_while_a_key_is_waiting() {
+    let (mut cpu, _) = machine(b"ab");
+
+    // the key stays there until KBDR is read
+    assert_eq!(cpu.read_memory(KBSR).unwrap() as u16, 0x8000);
+    assert_eq!(cpu.read_memory(KBSR).unwrap() as u16, 0x8000);
+    assert_eq!(cpu.read_memory(KBDR).unwrap(), 'a' as i16);
+
+    assert_eq!(cpu.read_memory(KBSR).unwrap() as u16, 0x8000);
+    assert_eq!(cpu.read_memory(KBDR).unwrap(), 'b' as i16);
+
+    // with no key waiting the status says so at once, and reading KBDR gives zero
+    assert_eq!(cpu.read_memory(KBSR).unwrap(), 0);
+    assert_eq!(cpu.read_memory(KBDR).unwrap(), 0);
+}
+
+#[test]
+fn polling_spins_until_a_key_is_typed() {
+    let program = r#"
+        .ORIG x3000
+        AND R3, R3, #0
+KEY     ADD R3, R3, #1
+        LDI R1, KBSRP
+        BRzp KEY
+        LDI R0, KBDRP
+        HALT
+KBSRP   .FILL xFE00
+KBDRP   .FILL xFE02
+        .END
+    "#;
+    let (mut cpu, console) = machine(b"");
+    cpu.load_program(&assemble(program).unwrap().program);
+
+    // nothing has been typed, so the program keeps polling rather than waiting
+    for _ in 0..30 {
+        cpu.run_one_instruction_cycle().unwrap();
+    }
+    assert!(cpu.running);
+    assert!(cpu.reg[3] > 3, "polled {} times", cpu.reg[3]);
+    let polls = cpu.reg[3];
+
+    console.push_input(b"x");
+    run(&mut cpu);
+    assert_eq!(cpu.reg[0], 'x' as i16);
+    assert_eq!(cpu.reg[3], polls + 1);
+}
+
+#[test]
+fn polling_faults_once_the_input_has_run_out_for_good() {
+    let input = std::env::temp_dir().join(format!("lc3-devices-test-{}", std::process::id()));
+    std::fs::write(&input, b"a").unwrap();
+    let mut cpu = CPU::without_operating_system();
+    cpu.console = Box::new(FileConsole::open(&input).unwrap());
+
+    assert_eq!(cpu.read_memory(KBSR).unwrap() as u16, 0x8000);
+    assert_eq!(cpu.read_memory(KBDR).unwrap(), 'a' as i16);
+    match cpu.read_memory(KBSR) {
+        Err(LC3Error::EndOfInput) => (),
+        other => panic!("expected the end of the input, got {:?}", other)
+    }
+}
+
+#[test]
+fn the_keyboard_keeps_its_interrupt_enable_bit() {
+    let (mut cpu, _) = machine(b"a");
+
+    cpu.write_memory(KBSR, 0x4000);
+    assert_eq!(cpu.mem.peek(KBSR) as u16, 0x4000);
+    assert_eq!(cpu.read_memory(KBSR).unwrap() as u16, 0xC000);
+
+    // a key with interrupts enabled asks for one
+    let mut interrupts = InterruptController::new();
+    cpu.mem.tick(&mut interrupts, &mut *cpu.console);
+    assert_eq!(interrupts.pending()[0].vector, KEYBOARD_INTERRUPT_VECTOR);
+
+    // the ready bit belongs to the keyboard; writing it only disables the interrupt
+    cpu.write_memory(KBSR, -0x8000);
+    assert_eq!(cpu.mem.peek(KBSR) as u16, 0x8000);
+    cpu.mem.tick(&mut interrupts, &mut *cpu.console);
+    assert!(interrupts.pending().is_empty());
+}
+
+#[test]
+fn the_display_is_always_ready_and_prints_what_is_written() {
+    let (mut cpu, console) = machine(b"");
+
+    assert_eq!(cpu.read_memory(DSR).unwrap() as u16, 0x8000);
+    cpu.write_memory(DDR, 'h' as i16);
+    cpu.write_memory(DDR, 'i' as i16);
+
+    assert_eq!(console.output_string(), "hi");
+    assert_eq!(cpu.read_memory(DSR).unwrap() as u16, 0x8000);
+    assert_eq!(cpu.mem.peek(DDR), 'i' as i16);
+}
+
+#[test]
+fn clearing_the_clock_stops_the_machine() {
+    let program = r#"
+        .ORIG x3000
+        LDI R0, MCRP
+        LD R1, MASK
+        AND R0, R0, R1
+        STI R0, MCRP
+        ADD R2, R2, #1
+        .FILL xFFFF
+MCRP    .FILL xFFFE
+MASK    .FILL x7FFF
+        .END
+    "#;
+    let mut cpu = CPU::without_operating_system();
+    cpu.load_program(&assemble(program).unwrap().program);
+    assert_eq!(cpu.mem.peek(MCR) as u16, 0x8000);
+
+    run(&mut cpu);
+    assert_eq!(cpu.pc, 0x3004);
+    assert_eq!(cpu.reg[2], 0);
+    assert_eq!(cpu.mem.peek(MCR), 0);
+
+    // starting again sets the clock running
+    cpu.set_pc_address(0x3004);
+    assert!(cpu.running);
+    assert_eq!(cpu.mem.peek(MCR) as u16, 0x8000);
+}
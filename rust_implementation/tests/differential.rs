@@ -0,0 +1,468 @@
+// Differential tests: runs the C simulator in ../c_implementation and this one
+// side by side on the same programs and checks that they agree on the machine
+// state after every instruction.  The tests are skipped when no C compiler is
+// available.
+//
+// Known differences in the C simulator that the programs here steer around:
+//   * its condition codes are numbered backwards (N = 1, P = 4), so BRn, BRp,
+//     BRzp, and BRnz test the wrong flag (BRz, BRnp, and BRnzp are unaffected)
+//   * STR stores to the register number plus the offset instead of the register's value
+//   * loads and stores outside x0000 - xFFFF (e.g. through a negative register) are out of bounds
+//   * RTI and the reserved opcode do nothing, and unknown traps halt
+//   * HALT sets the condition code to P
+
+extern crate lc3;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use lc3::{assemble, disassemble, Address, BufferConsole, Instruction, Program, CPU};
+
+//////////////////////////////////////////////////////
+// HARNESS
+//////////////////////////////////////////////////////
+
+const HALT: Instruction = 0xF025;
+
+#[derive(Clone, Debug, PartialEq)]
+struct MachineState {
+    pc: Address,
+    ir: Instruction,
+    cc: char,
+    running: bool,
+    registers: Vec<u16>,
+    memory: BTreeMap<Address, u16> // non-zero words only
+}
+
+// The machine state before and after each instruction, plus what was executed.
+struct Run {
+    states: Vec<MachineState>,
+    trace: Vec<(Address, Instruction)>
+}
+
+#[derive(Debug)]
+struct Divergence {
+    step: usize,
+    pc: Address,
+    ir: Instruction,
+    rust: MachineState,
+    c: MachineState
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "first divergent instruction is #{} at x{:04X}: {:04X} ({})",
+                 self.step, self.pc, self.ir, disassemble(self.ir, self.pc))?;
+
+        if self.rust.pc != self.c.pc { writeln!(f, "  PC: rust x{:04X}, c x{:04X}", self.rust.pc, self.c.pc)? }
+        if self.rust.ir != self.c.ir { writeln!(f, "  IR: rust x{:04X}, c x{:04X}", self.rust.ir, self.c.ir)? }
+        if self.rust.cc != self.c.cc { writeln!(f, "  CC: rust {}, c {}", self.rust.cc, self.c.cc)? }
+        if self.rust.running != self.c.running {
+            writeln!(f, "  running: rust {}, c {}", self.rust.running, self.c.running)?
+        }
+
+        for reg in 0..8 {
+            if self.rust.registers[reg] != self.c.registers[reg] {
+                writeln!(f, "  R{}: rust x{:04X}, c x{:04X}", reg, self.rust.registers[reg], self.c.registers[reg])?
+            }
+        }
+
+        let addresses = self.rust.memory.keys().chain(self.c.memory.keys()).collect::<std::collections::BTreeSet<_>>();
+        for addr in addresses {
+            let rust = self.rust.memory.get(addr).cloned().unwrap_or(0);
+            let c = self.c.memory.get(addr).cloned().unwrap_or(0);
+            if rust != c { writeln!(f, "  M[x{:04X}]: rust x{:04X}, c x{:04X}", addr, rust, c)? }
+        }
+
+        Ok(())
+    }
+}
+
+// Runs both simulators for up to steps instructions and returns the first
+// instruction after which they disagree.
+fn compare(program: &Program, steps: usize) -> Option<Result<(), Divergence>> {
+    let c = run_c(program, steps)?;
+    let rust = run_rust(program, steps);
+
+    // HALT sets CC to P in the C simulator; that's a quirk, not a bug worth reporting
+    let mut compare_cc = true;
+
+    for step in 0..steps {
+        let (pc, ir) = rust.trace[step];
+        if ir == HALT { compare_cc = false }
+
+        let mut rust_state = rust.states[step + 1].clone();
+        let c_state = c.states[step + 1].clone();
+        if !compare_cc { rust_state.cc = c_state.cc }
+
+        if rust.trace[step] != c.trace[step] || rust_state != c_state {
+            return Some(Err(Divergence { step, pc, ir, rust: rust_state, c: c_state }))
+        }
+    }
+
+    Some(Ok(()))
+}
+
+fn assert_agree(program: &Program, steps: usize) {
+    if let Some(Err(divergence)) = compare(program, steps) {
+        panic!("the Rust and C simulators disagree\n{}", divergence);
+    }
+}
+
+fn run_rust(program: &Program, steps: usize) -> Run {
+    let mut cpu = CPU::without_operating_system();
+    cpu.console = Box::new(BufferConsole::default());
+    cpu.load_program(program);
+
+    let mut run = Run { states: vec![rust_state(&cpu)], trace: Vec::new() };
+    for _ in 0..steps {
+        if cpu.running {
+            let pc = cpu.pc;
+            cpu.run_one_instruction_cycle().expect("the Rust simulator faulted");
+            run.trace.push((pc, cpu.ir));
+        } else {
+            run.trace.push((cpu.pc, cpu.ir));
+        }
+        run.states.push(rust_state(&cpu));
+    }
+
+    run
+}
+
+fn rust_state(cpu: &CPU) -> MachineState {
+    MachineState {
+        pc: cpu.pc,
+        ir: cpu.ir,
+        cc: format!("{:?}", cpu.cc).chars().next().unwrap(),
+        running: cpu.running,
+        registers: cpu.reg.vals.iter().map(|&value| value as u16).collect(),
+        memory: cpu.mem.ram.vals.iter().enumerate()
+            .filter(|&(_, &value)| value != 0)
+            .map(|(addr, &value)| (addr as Address, value as u16))
+            .collect()
+    }
+}
+
+// Drives the C simulator's console: one instruction then a dump, steps times.
+fn run_c(program: &Program, steps: usize) -> Option<Run> {
+    let simulator = c_simulator()?;
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let hex_file = scratch_dir().join(format!("program{}.hex", RUNS.fetch_add(1, Ordering::SeqCst)));
+    program.write_hex_file(&hex_file).unwrap();
+
+    let mut child = Command::new(simulator)
+        .arg(&hex_file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("couldn't start the C simulator");
+
+    let commands = "1\nd\n".repeat(steps) + "q\n";
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    // the simulator dumps everything once on start up and then once per step
+    let states = output.split("Control Unit:").skip(1).map(parse_c_dump).collect::<Vec<_>>();
+    assert_eq!(states.len(), steps + 1, "unexpected output from the C simulator:\n{}", output);
+
+    // a halted CPU doesn't execute anything, so repeat its last instruction like run_rust does
+    let mut trace = parse_c_trace(&output);
+    while trace.len() < steps {
+        let last = states[trace.len()].clone();
+        trace.push((last.pc, last.ir));
+    }
+
+    Some(Run { states, trace })
+}
+
+// PC = x3000    IR = x0000    CC = Z    RUNNING: 1
+// R0: x0000  0	R1: x0000  0 ...
+// mem:    (addresses x0000 - xFFFF)
+// x3000: x1261	4705
+fn parse_c_dump(dump: &str) -> MachineState {
+    let field = |name: &str| {
+        let start = dump.find(name).unwrap() + name.len();
+        dump[start..].split_whitespace().next().unwrap().to_owned()
+    };
+    let hex = |text: &str| u16::from_str_radix(text.trim_start_matches('x'), 16).unwrap();
+
+    let registers = (0..8).map(|reg| hex(&field(&format!("R{}: ", reg)))).collect();
+
+    let memory = dump.lines()
+        .skip_while(|line| !line.starts_with("mem:"))
+        .skip(1)
+        .take_while(|line| line.starts_with('x'))
+        .map(|line| {
+            let mut words = line.split(|c: char| c == ':' || c.is_whitespace()).filter(|word| !word.is_empty());
+            (hex(words.next().unwrap()), hex(words.next().unwrap()))
+        })
+        .collect();
+
+    MachineState {
+        pc: hex(&field("PC = ")),
+        ir: hex(&field("IR = ")),
+        cc: field("CC = ").chars().next().unwrap(),
+        running: field("RUNNING: ") == "1",
+        registers,
+        memory
+    }
+}
+
+// Each executed instruction is logged as "x3000: x1261 | ADD ...".
+fn parse_c_trace(output: &str) -> Vec<(Address, Instruction)> {
+    output.lines()
+        .map(|line| line.trim_start_matches("> "))
+        .filter(|line| line.len() > 15 && line.starts_with('x') && &line[5..8] == ": x" && &line[12..15] == " | ")
+        .map(|line| (u16::from_str_radix(&line[1..5], 16).unwrap(), u16::from_str_radix(&line[8..12], 16).unwrap()))
+        .collect()
+}
+
+fn scratch_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("differential");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Compiles the C simulator once per test run; None if there's no C compiler.
+fn c_simulator() -> Option<PathBuf> {
+    static SIMULATOR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+    SIMULATOR.get_or_init(|| {
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../c_implementation/src/main.c");
+        let binary = scratch_dir().join("lc3_c");
+        let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+        let compiled = Command::new(&compiler)
+            .args(["-std=c99", "-w", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+
+        if compiled {
+            Some(binary)
+        } else {
+            eprintln!("skipping differential tests: couldn't compile {} with {}", source.display(), compiler);
+            None
+        }
+    }).clone()
+}
+
+fn program(source: &str) -> Program {
+    assemble(source).unwrap().program
+}
+
+
+//////////////////////////////////////////////////////
+// PROGRAMS
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn arithmetic_and_loops_agree() {
+    // 7 * 5 by repeated addition, then a few more ALU operations on the result
+    assert_agree(&program(r#"
+        .ORIG x3000
+        AND R0, R0, #0
+        LD R1, SEVEN
+        LD R2, FIVE
+LOOP    ADD R0, R0, R1
+        ADD R2, R2, #-1
+        BRnp LOOP
+        ST R0, RESULT
+        NOT R3, R0
+        ADD R3, R3, #1
+        AND R4, R3, #-16
+        ADD R5, R3, R0
+        BRz DONE
+        ADD R6, R6, #15
+DONE    LEA R7, RESULT
+        HALT
+SEVEN   .FILL 7
+FIVE    .FILL 5
+RESULT  .BLKW 1
+        .END
+    "#), 40);
+}
+
+#[test]
+fn memory_addressing_modes_agree() {
+    assert_agree(&program(r#"
+        .ORIG x3000
+        LEA R1, TABLE
+        LDR R2, R1, #0
+        LDR R3, R1, #2
+        LDI R4, POINTER
+        ADD R4, R4, #3
+        STI R4, POINTER
+        LDI R5, POINTER
+        LD R6, TABLE
+        ST R6, COPY
+        HALT
+TABLE   .FILL x1234
+        .FILL -2
+        .FILL x7FFF
+POINTER .FILL x3100
+COPY    .BLKW 1
+        .END
+    "#), 12);
+}
+
+#[test]
+fn subroutines_agree() {
+    assert_agree(&program(r#"
+        .ORIG x3000
+        AND R0, R0, #0
+        JSR INCREMENT
+        JSR INCREMENT
+        LEA R2, INCREMENT
+        JSRR R2
+        LEA R3, DONE
+        JMP R3
+        ADD R0, R0, #10
+DONE    HALT
+INCREMENT
+        ADD R0, R0, #1
+        RET
+        .END
+    "#), 20);
+}
+
+#[test]
+fn output_traps_agree() {
+    assert_agree(&program(r#"
+        .ORIG x3000
+        LEA R0, MESSAGE
+        PUTS
+        LD R0, BANG
+        OUT
+        HALT
+MESSAGE .STRINGZ "hi"
+BANG    .FILL x21
+        .END
+    "#), 6);
+}
+
+// The harness has to actually notice when the simulators disagree; the C
+// simulator's reversed condition codes make BRp misbehave.
+#[test]
+fn reports_the_first_divergent_instruction() {
+    let program = program(r#"
+        .ORIG x3000
+        ADD R1, R1, #1
+        BRp SKIP
+        ADD R2, R2, #1
+SKIP    HALT
+        .END
+    "#);
+
+    match compare(&program, 4) {
+        Some(Err(divergence)) => {
+            assert_eq!(divergence.step, 1);
+            assert_eq!(divergence.pc, 0x3001);
+            assert_eq!(divergence.rust.pc, 0x3003);
+            assert_eq!(divergence.c.pc, 0x3002);
+        },
+        Some(Ok(())) => panic!("the simulators should disagree on BRp"),
+        None => {}
+    }
+}
+
+
+//////////////////////////////////////////////////////
+// RANDOM INSTRUCTION STREAMS
+//////////////////////////////////////////////////////
+
+
+// xorshift; good enough to make varied but reproducible programs
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+
+    fn between(&mut self, low: i32, high: i32) -> i32 {
+        low + self.below((high - low + 1) as u32) as i32
+    }
+}
+
+const ORIGIN: Address = 0x3000;
+const CODE_LENGTH: usize = 48;
+const DATA_LENGTH: usize = 16;
+
+// Only instructions both simulators implement the same way: no STR, no
+// register-relative addressing (registers can hold anything), and only the
+// branch conditions that survive the C simulator's swapped N and P.  Control
+// flow stays inside the code and stores stay inside the data after it.
+fn random_program(seed: u32) -> Program {
+    let mut random = Random(seed);
+    let data_start = ORIGIN as i32 + CODE_LENGTH as i32;
+    let mut words = Vec::new();
+
+    for index in 0..CODE_LENGTH - 1 {
+        let next_pc = ORIGIN as i32 + index as i32 + 1;
+        let dr = random.below(8);
+        let sr1 = random.below(8);
+        let sr2 = random.below(8);
+
+        let word = match random.below(10) {
+            0 => 0x1000 | dr << 9 | sr1 << 6 | sr2,
+            1 => 0x1000 | dr << 9 | sr1 << 6 | 1 << 5 | (random.between(-16, 15) as u32 & 0x1F),
+            2 => 0x5000 | dr << 9 | sr1 << 6 | sr2,
+            3 => 0x5000 | dr << 9 | sr1 << 6 | 1 << 5 | (random.between(-16, 15) as u32 & 0x1F),
+            4 => 0x9000 | dr << 9 | sr1 << 6 | 0x3F,
+            5 => 0xE000 | dr << 9 | (random.between(-64, 64) as u32 & 0x1FF),
+            6 => 0x2000 | dr << 9 | (random.between(-64, 64) as u32 & 0x1FF),
+            7 => {
+                let target = data_start + random.below(DATA_LENGTH as u32) as i32;
+                0x3000 | dr << 9 | ((target - next_pc) as u32 & 0x1FF)
+            },
+            8 => {
+                let nzp = [0b000, 0b010, 0b101, 0b111][random.below(4) as usize];
+                let target = ORIGIN as i32 + random.below(CODE_LENGTH as u32) as i32;
+                nzp << 9 | ((target - next_pc) as u32 & 0x1FF)
+            },
+            _ => {
+                let target = ORIGIN as i32 + random.below(CODE_LENGTH as u32) as i32;
+                0x4800 | ((target - next_pc) as u32 & 0x7FF)
+            }
+        };
+        words.push(word as Instruction);
+    }
+
+    // loop back to the start rather than running into the data
+    words.push((0x0E00 | ((-(CODE_LENGTH as i32)) as u32 & 0x1FF)) as Instruction);
+
+    for _ in 0..DATA_LENGTH {
+        words.push(random.next() as Instruction);
+    }
+
+    Program::new(ORIGIN, words)
+}
+
+#[test]
+fn random_instruction_streams_agree() {
+    for seed in 1..=30 {
+        let program = random_program(seed);
+        if let Some(Err(divergence)) = compare(&program, 120) {
+            panic!("the Rust and C simulators disagree on random program {}\n{}\n{}",
+                   seed, divergence, program.to_hex_string());
+        }
+    }
+}
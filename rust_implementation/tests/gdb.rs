@@ -0,0 +1,307 @@
+// Drives the GDB stub over a loopback socket the same way GDB would.
+
+extern crate lc3;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use lc3::{assemble, BufferConsole, Debugger, GdbStub, Transport, CPU};
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R0, R0, #0      ; x3000
+        ADD R0, R0, #5      ; x3001
+        ST R0, VALUE        ; x3002
+        ADD R1, R0, R0      ; x3003
+        HALT                ; x3004
+VALUE   .FILL 0             ; x3005
+        .END
+"#;
+
+const LOOP: &str = ".ORIG x3000\nBRnzp #-1\n.END";
+
+// Serves a single connection from a thread of its own.  The debugger has to
+// be made on that thread since the CPU's console isn't Send.
+fn serve<T, F>(source: &'static str, accept: F) -> JoinHandle<()>
+    where T: Transport, F: FnOnce() -> T + Send + 'static {
+    thread::spawn(move || {
+        let mut cpu = CPU::without_operating_system();
+        cpu.console = Box::new(BufferConsole::default());
+        cpu.load_program(&assemble(source).unwrap().program);
+
+        let mut debugger = Debugger::new(cpu);
+        let transport = accept();
+        GdbStub::new(&mut debugger, transport).serve().expect("the stub should finish cleanly");
+    })
+}
+
+struct Client<S: Read + Write> {
+    stream: S,
+    acknowledge: bool
+}
+
+impl Client<TcpStream> {
+    fn connect(source: &'static str) -> (Client<TcpStream>, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = serve(source, move || {
+            let stream = listener.accept().unwrap().0;
+            stream.set_nodelay(true).unwrap();
+            stream
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        (Client { stream, acknowledge: true }, server)
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        self.stream.flush().unwrap();
+
+        if self.acknowledge {
+            assert_eq!(self.read_byte(), b'+', "{} wasn't acknowledged", data);
+        }
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte)
+            }
+        }
+
+        let mut checksum = [0u8; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let expected = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(String::from_utf8_lossy(&checksum), format!("{:02x}", expected));
+
+        if self.acknowledge { self.stream.write_all(b"+").unwrap() }
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn detach(mut self, server: JoinHandle<()>) {
+        assert_eq!(self.request("D"), "OK");
+        server.join().unwrap();
+    }
+}
+
+
+#[test]
+fn reports_registers_and_memory() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert!(gdb.request("qSupported:swbreak+").contains("PacketSize="));
+    assert_eq!(gdb.request("?"), "S05");
+
+    // R0 - R7, PC, then PSR (user mode, CC=Z), each low byte first
+    assert_eq!(gdb.request("g"), format!("{}00300280", "0000".repeat(8)));
+    assert_eq!(gdb.request("p8"), "0030");
+    assert_eq!(gdb.request("pa"), "E01");
+
+    // AND R0, R0, #0 and ADD R0, R0, #5: lengths are in words, like addresses
+    assert_eq!(gdb.request("m3000,2"), "20502510");
+    assert_eq!(gdb.request("m3001,1"), "2510");
+    gdb.detach(server);
+}
+
+#[test]
+fn changes_registers_and_memory() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert_eq!(gdb.request("P1=ffff"), "OK");
+    assert_eq!(gdb.request("p1"), "ffff");
+    assert_eq!(gdb.request("P8=0330"), "OK");
+    assert_eq!(gdb.request("p8"), "0330");
+
+    assert_eq!(gdb.request("M3005,1:3412"), "OK");
+    assert_eq!(gdb.request("m3005,1"), "3412");
+
+    // the data has to be whole words, as many as the length says
+    assert_eq!(gdb.request("M3005,1:ff"), "E01");
+    assert_eq!(gdb.request("M3005,2:3412"), "E01");
+    assert_eq!(gdb.request("m3005,1"), "3412");
+
+    let registers = "0100020003000400050006000700080000310180";
+    assert_eq!(gdb.request(&format!("G{}", registers)), "OK");
+    assert_eq!(gdb.request("g"), registers);
+    gdb.detach(server);
+}
+
+#[test]
+fn rejects_lengths_out_of_range() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    // PacketSize=1000 leaves room for reading or writing x400 words at a time
+    assert_eq!(gdb.request("m3000,400").len(), 0x1000);
+    assert_eq!(gdb.request("m3000,401"), "E01");
+    assert_eq!(gdb.request("m3000,ffffffff"), "E01");
+    assert_eq!(gdb.request("M3000,401:0000"), "E01");
+
+    // a watchpoint can cover at most all of memory, cut off at its end
+    assert_eq!(gdb.request("Z2,3000,10000"), "OK");
+    assert_eq!(gdb.request("z2,3000,10000"), "OK");
+    assert_eq!(gdb.request("Z3,0,10000"), "OK");
+    assert_eq!(gdb.request("Z4,3000,10001"), "E01");
+    assert_eq!(gdb.request("Z2,3000,ffffffff"), "E01");
+    gdb.detach(server);
+}
+
+#[test]
+fn single_steps() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p8"), "0130");
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p0"), "0500");
+
+    // stepping from somewhere else
+    assert_eq!(gdb.request("s3003"), "S05");
+    assert_eq!(gdb.request("p1"), "0a00");
+    gdb.detach(server);
+}
+
+#[test]
+fn continues_to_breakpoints_and_halts() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert_eq!(gdb.request("Z0,3003,2"), "OK");
+    assert_eq!(gdb.request("c"), "T05swbreak:;");
+    assert_eq!(gdb.request("p8"), "0330");
+    assert_eq!(gdb.request("m3005,1"), "0500");
+
+    assert_eq!(gdb.request("z0,3003,2"), "OK");
+    assert_eq!(gdb.request("c"), "W00");
+    assert_eq!(gdb.request("p1"), "0a00");
+    gdb.detach(server);
+}
+
+#[test]
+fn stops_on_watchpoints() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert_eq!(gdb.request("Z2,3005,1"), "OK");
+    assert_eq!(gdb.request("c"), "T05watch:3005;");
+    assert_eq!(gdb.request("p8"), "0330");
+
+    assert_eq!(gdb.request("z2,3005,1"), "OK");
+    assert_eq!(gdb.request("c"), "W00");
+    gdb.detach(server);
+}
+
+#[test]
+fn steps_and_continues_backwards() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert!(gdb.request("qSupported").contains("ReverseStep+"));
+    assert_eq!(gdb.request("c"), "W00");
+    assert_eq!(gdb.request("bs"), "S05");
+    assert_eq!(gdb.request("p8"), "0430");
+
+    assert_eq!(gdb.request("Z0,3002,2"), "OK");
+    assert_eq!(gdb.request("bc"), "T05swbreak:;");
+    assert_eq!(gdb.request("m3005,1"), "0000");
+
+    assert_eq!(gdb.request("bc"), "T05replaylog:begin;");
+    assert_eq!(gdb.request("p8"), "0030");
+    gdb.detach(server);
+}
+
+#[test]
+fn interrupts_a_running_program() {
+    let (mut gdb, server) = Client::connect(LOOP);
+
+    gdb.send("c");
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.receive(), "S02");
+    assert_eq!(gdb.request("p8"), "0030");
+    gdb.detach(server);
+}
+
+#[test]
+fn keeps_what_gdb_sends_while_the_program_runs() {
+    let (mut gdb, server) = Client::connect(LOOP);
+
+    // a packet sent before the interrupt is answered once the program stops
+    gdb.send("c");
+    gdb.stream.write_all(b"$p8#a8").unwrap();
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.receive(), "S02");
+    assert_eq!(gdb.read_byte(), b'+');
+    assert_eq!(gdb.receive(), "0030");
+    gdb.detach(server);
+}
+
+#[test]
+fn describes_the_target_in_words() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert!(gdb.request("qSupported").contains("qXfer:features:read+"));
+
+    let description = gdb.request("qXfer:features:read:target.xml:0,1000");
+    assert!(description.starts_with("l<?xml"), "{}", description);
+    assert!(description.contains("count 16-bit words"));
+    assert_eq!(description.matches("<reg ").count(), 10);
+
+    // and in pieces, for GDB's smaller reads
+    let first = gdb.request("qXfer:features:read:target.xml:0,10");
+    assert_eq!(first, format!("m{}", &description[1..0x11]));
+    gdb.detach(server);
+}
+
+#[test]
+fn works_without_acknowledgements() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+    gdb.acknowledge = false;
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p8"), "0130");
+    gdb.detach(server);
+}
+
+#[test]
+fn resends_corrupted_packets() {
+    let (mut gdb, server) = Client::connect(PROGRAM);
+
+    gdb.stream.write_all(b"$p8#00").unwrap();
+    assert_eq!(gdb.read_byte(), b'-');
+    assert_eq!(gdb.request("p8"), "0030");
+    gdb.detach(server);
+}
+
+#[cfg(unix)]
+#[test]
+fn serves_unix_sockets() {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = std::env::temp_dir().join(format!("lc3-gdb-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = serve(PROGRAM, move || listener.accept().unwrap().0);
+
+    let mut gdb = Client { stream: UnixStream::connect(&path).unwrap(), acknowledge: true };
+    assert_eq!(gdb.request("s"), "S05");
+    assert_eq!(gdb.request("p8"), "0130");
+    gdb.detach(server);
+
+    std::fs::remove_file(&path).unwrap();
+}
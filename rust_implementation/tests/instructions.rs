@@ -0,0 +1,332 @@
+// One or more tests for every operation and addressing mode, run against a
+// CPU with native traps unless the operating system is the point of the test.
+
+extern crate lc3;
+
+mod common;
+
+use common::{execute, CpuBuilder, ORIGIN};
+use lc3::{ConditionCode, LC3Error, Privilege, Word};
+use lc3::cpu::{AccessKind, SUPERVISOR_STACK_START};
+
+
+//////////////////////////////////////////////////////
+// OPERATE INSTRUCTIONS
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn add_registers() {
+    // ADD R1, R2, R3
+    let machine = execute(0x1283, CpuBuilder::new().reg(2, 20).reg(3, 22));
+    assert_eq!(machine.reg(1), 42);
+    assert_eq!(machine.cpu.cc, ConditionCode::P);
+}
+
+#[test]
+fn add_negative_immediate() {
+    // ADD R1, R1, #-16
+    let machine = execute(0x1270, CpuBuilder::new().reg(1, 10));
+    assert_eq!(machine.reg(1), -6);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+}
+
+#[test]
+fn add_positive_immediate() {
+    // ADD R0, R0, #15
+    let machine = execute(0x102F, CpuBuilder::new().reg(0, -15));
+    assert_eq!(machine.reg(0), 0);
+    assert_eq!(machine.cpu.cc, ConditionCode::Z);
+}
+
+#[test]
+fn add_wraps_around_on_overflow() {
+    // ADD R0, R0, #1
+    let machine = execute(0x1021, CpuBuilder::new().reg(0, Word::MAX));
+    assert_eq!(machine.reg(0), Word::MIN);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+}
+
+#[test]
+fn and_registers() {
+    // AND R2, R0, R1
+    let machine = execute(0x5401, CpuBuilder::new().reg(0, 0b1100).reg(1, 0b1010));
+    assert_eq!(machine.reg(2), 0b1000);
+    assert_eq!(machine.cpu.cc, ConditionCode::P);
+}
+
+#[test]
+fn and_immediate_is_sign_extended() {
+    // AND R3, R3, #-2 (keeps every bit but the lowest)
+    let machine = execute(0x56FE, CpuBuilder::new().reg(3, -1));
+    assert_eq!(machine.reg(3), -2);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+}
+
+#[test]
+fn and_with_zero_clears_the_register() {
+    // AND R4, R4, #0
+    let machine = execute(0x5920, CpuBuilder::new().reg(4, 1234).cc(ConditionCode::P));
+    assert_eq!(machine.reg(4), 0);
+    assert_eq!(machine.cpu.cc, ConditionCode::Z);
+}
+
+#[test]
+fn not_inverts_every_bit() {
+    // NOT R5, R6
+    let machine = execute(0x9BBF, CpuBuilder::new().reg(6, 0x00FF));
+    assert_eq!(machine.reg(5), 0xFF00u16 as Word);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+
+    let machine = execute(0x9BBF, CpuBuilder::new().reg(6, -1));
+    assert_eq!(machine.reg(5), 0);
+    assert_eq!(machine.cpu.cc, ConditionCode::Z);
+}
+
+#[test]
+fn lea_loads_an_address_and_sets_the_condition_code() {
+    // LEA R0, #-3
+    let machine = execute(0xE1FD, CpuBuilder::new());
+    assert_eq!(machine.reg(0), (ORIGIN + 1 - 3) as Word);
+    assert_eq!(machine.cpu.cc, ConditionCode::P);
+}
+
+
+//////////////////////////////////////////////////////
+// CONDITION CODES
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn set_dr_updates_the_condition_code() {
+    let mut cpu = CpuBuilder::new().build().cpu;
+
+    cpu.set_dr(0, -5);
+    assert_eq!((cpu.reg[0], cpu.cc), (-5, ConditionCode::N));
+
+    cpu.set_dr(1, 0);
+    assert_eq!((cpu.reg[1], cpu.cc), (0, ConditionCode::Z));
+
+    cpu.set_dr(2, 7);
+    assert_eq!((cpu.reg[2], cpu.cc), (7, ConditionCode::P));
+}
+
+#[test]
+fn stores_leave_the_condition_code_alone() {
+    // ST R0, #5
+    let machine = execute(0x3005, CpuBuilder::new().reg(0, -1).cc(ConditionCode::Z));
+    assert_eq!(machine.cpu.cc, ConditionCode::Z);
+}
+
+
+//////////////////////////////////////////////////////
+// CONTROL INSTRUCTIONS
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn br_is_taken_only_when_a_tested_flag_is_set() {
+    let conditions = [ConditionCode::N, ConditionCode::Z, ConditionCode::P];
+
+    for nzp in 0..8u16 {
+        for &cc in &conditions {
+            // BR<nzp> #4
+            let machine = execute(nzp << 9 | 4, CpuBuilder::new().cc(cc));
+            let taken = nzp as i32 & cc.bit() != 0;
+            let expected = if taken { ORIGIN + 5 } else { ORIGIN + 1 };
+            assert_eq!(machine.cpu.pc, expected, "nzp = {:03b}, cc = {:?}", nzp, cc);
+        }
+    }
+}
+
+#[test]
+fn br_offsets_are_signed() {
+    // BRnzp #-256
+    let machine = execute(0x0F00, CpuBuilder::new());
+    assert_eq!(machine.cpu.pc, ORIGIN + 1 - 256);
+}
+
+#[test]
+fn jmp_jumps_to_the_base_register() {
+    // JMP R3
+    let machine = execute(0xC0C0, CpuBuilder::new().reg(3, 0x4000));
+    assert_eq!(machine.cpu.pc, 0x4000);
+}
+
+#[test]
+fn ret_jumps_to_r7() {
+    // RET
+    let machine = execute(0xC1C0, CpuBuilder::new().reg(7, 0x3456));
+    assert_eq!(machine.cpu.pc, 0x3456);
+}
+
+#[test]
+fn jsr_saves_the_return_address() {
+    // JSR #-1024
+    let machine = execute(0x4C00, CpuBuilder::new());
+    assert_eq!(machine.cpu.pc, ORIGIN + 1 - 1024);
+    assert_eq!(machine.reg(7), (ORIGIN + 1) as Word);
+}
+
+#[test]
+fn jsrr_jumps_to_the_base_register() {
+    // JSRR R2
+    let machine = execute(0x4080, CpuBuilder::new().reg(2, 0x5000));
+    assert_eq!(machine.cpu.pc, 0x5000);
+    assert_eq!(machine.reg(7), (ORIGIN + 1) as Word);
+}
+
+#[test]
+fn jsrr_through_r7_uses_its_old_value() {
+    // JSRR R7
+    let machine = execute(0x41C0, CpuBuilder::new().reg(7, 0x5000));
+    assert_eq!(machine.cpu.pc, 0x5000);
+    assert_eq!(machine.reg(7), (ORIGIN + 1) as Word);
+}
+
+#[test]
+fn rti_returns_from_supervisor_mode() {
+    let user_stack = 0x4000;
+    let supervisor_stack = SUPERVISOR_STACK_START - 2;
+
+    // the PC and a user mode PSR with CC = N, as an interrupt would have left them
+    let mut machine = CpuBuilder::new()
+        .privilege(Privilege::Supervisor)
+        .reg(6, supervisor_stack)
+        .mem(supervisor_stack as u16, 0x3456)
+        .mem(supervisor_stack as u16 + 1, (0x8000u16 | 0b100) as Word)
+        .instruction(0x8000)
+        .build();
+    machine.cpu.saved_usp = user_stack;
+    machine.step();
+
+    assert_eq!(machine.cpu.pc, 0x3456);
+    assert_eq!(machine.cpu.privilege, Privilege::User);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+    assert_eq!(machine.reg(6), user_stack);
+    assert_eq!(machine.cpu.saved_ssp, SUPERVISOR_STACK_START);
+}
+
+#[test]
+fn rti_in_user_mode_is_a_privilege_mode_violation() {
+    // without an operating system there's nothing to handle the exception
+    let mut machine = CpuBuilder::new().instruction(0x8000).build();
+    match machine.cpu.run_one_instruction_cycle() {
+        Err(LC3Error::MissingServiceRoutine(0x00)) => {},
+        other => panic!("expected a missing service routine, got {:?}", other.err())
+    }
+
+    // the bundled operating system stops the machine
+    let mut machine = CpuBuilder::with_operating_system().instruction(0x8000).build();
+    machine.run();
+    assert_eq!(machine.cpu.last_exception, Some(0x00));
+    assert!(machine.output().contains("Privilege mode violation"));
+}
+
+#[test]
+fn the_reserved_opcode_is_illegal() {
+    let mut machine = CpuBuilder::new().instruction(0xD000).build();
+    match machine.cpu.run_one_instruction_cycle() {
+        Err(LC3Error::UnusedOpCode) => {},
+        other => panic!("expected an unused op code error, got {:?}", other.err())
+    }
+
+    let mut machine = CpuBuilder::with_operating_system().instruction(0xD000).build();
+    machine.run();
+    assert_eq!(machine.cpu.last_exception, Some(0x01));
+    assert!(machine.output().contains("Illegal opcode"));
+}
+
+
+//////////////////////////////////////////////////////
+// DATA MOVEMENT (ONE TEST PER ADDRESSING MODE)
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn ld_is_pc_relative() {
+    // LD R1, #-2
+    let machine = execute(0x23FE, CpuBuilder::new().mem(ORIGIN - 1, -300));
+    assert_eq!(machine.reg(1), -300);
+    assert_eq!(machine.cpu.cc, ConditionCode::N);
+}
+
+#[test]
+fn st_is_pc_relative() {
+    // ST R2, #255
+    let machine = execute(0x34FF, CpuBuilder::new().reg(2, 77));
+    assert_eq!(machine.mem(ORIGIN + 1 + 255), 77);
+}
+
+#[test]
+fn ldi_is_indirect() {
+    // LDI R3, #1
+    let machine = execute(0xA601, CpuBuilder::new().mem(ORIGIN + 2, 0x4000).mem(0x4000, 99));
+    assert_eq!(machine.reg(3), 99);
+    assert_eq!(machine.cpu.cc, ConditionCode::P);
+}
+
+#[test]
+fn sti_is_indirect() {
+    // STI R4, #1
+    let machine = execute(0xB801, CpuBuilder::new().reg(4, -8).mem(ORIGIN + 2, 0x4000));
+    assert_eq!(machine.mem(0x4000), -8);
+}
+
+#[test]
+fn ldr_is_base_plus_offset() {
+    // LDR R5, R1, #-32
+    let machine = execute(0x6A60, CpuBuilder::new().reg(1, 0x4020).mem(0x4000, 0x1234));
+    assert_eq!(machine.reg(5), 0x1234);
+}
+
+#[test]
+fn str_is_base_plus_offset() {
+    // STR R0, R6, #-1 (a push)
+    let machine = execute(0x71BF, CpuBuilder::new().reg(0, 42).reg(6, 0x4000));
+    assert_eq!(machine.mem(0x3FFF), 42);
+
+    // STR R0, R6, #31
+    let machine = execute(0x719F, CpuBuilder::new().reg(0, 42).reg(6, 0x4000));
+    assert_eq!(machine.mem(0x401F), 42);
+}
+
+#[test]
+fn data_accesses_are_recorded() {
+    // STR R0, R6, #0
+    let machine = execute(0x7180, CpuBuilder::new().reg(0, 5).reg(6, 0x4000).mem(0x4000, 3));
+    let access = machine.cpu.accesses[0];
+    assert_eq!(access.kind, AccessKind::Write);
+    assert_eq!((access.address, access.value, access.previous), (0x4000, 5, 3));
+}
+
+
+//////////////////////////////////////////////////////
+// WRAPAROUND
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn the_pc_wraps_around_the_top_of_memory() {
+    // ADD R0, R0, #1 at xFFFF
+    let machine = execute(0x1021, CpuBuilder::new().pc(0xFFFF));
+    assert_eq!(machine.cpu.pc, 0x0000);
+}
+
+#[test]
+fn pc_relative_addresses_wrap_around() {
+    // BRnzp #2 at xFFFE lands on x0001
+    let machine = execute(0x0E02, CpuBuilder::new().pc(0xFFFE));
+    assert_eq!(machine.cpu.pc, 0x0001);
+
+    // LD R0, #-2 at x0000 reads xFFFF
+    let machine = execute(0x21FE, CpuBuilder::new().pc(0x0000).mem(0xFFFF, 12));
+    assert_eq!(machine.reg(0), 12);
+}
+
+#[test]
+fn base_plus_offset_addresses_wrap_around() {
+    // LDR R0, R1, #1 with R1 = xFFFF reads x0000
+    let machine = execute(0x6041, CpuBuilder::new().reg(1, -1).mem(0x0000, 7));
+    assert_eq!(machine.reg(0), 7);
+}
@@ -0,0 +1,165 @@
+// Taking interrupts between instructions: the supervisor switch, returning
+// with RTI, the keyboard's interrupt, and how debuggers see them.
+
+extern crate lc3;
+
+mod common;
+
+use common::{CpuBuilder, Machine, SharedBuffer};
+use lc3::{Debugger, Privilege, StopReason, TraceFormat, TraceWriter, WatchKind, Watchpoint};
+use lc3::devices::{KBSR, KEYBOARD_INTERRUPT_PRIORITY, KEYBOARD_INTERRUPT_VECTOR};
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        ADD R0, R0, #1      ; x3000
+        ADD R0, R0, #1      ; x3001
+        ADD R0, R0, #1      ; x3002
+        HALT                ; x3003
+        .END
+"#;
+
+// Counts in R1, then returns to whatever was interrupted.
+const SERVICE_ROUTINE: &str = r#"
+        .ORIG x1000
+        ADD R1, R1, #1      ; x1000
+        RTI                 ; x1001
+        .END
+"#;
+
+const VECTOR: u8 = 0x81;
+const PRIORITY: u8 = 2;
+
+// The program at x3000 with the service routine installed for VECTOR.
+fn machine() -> Machine {
+    CpuBuilder::new()
+        .program(SERVICE_ROUTINE)
+        .mem(0x0100 + u16::from(VECTOR), 0x1000)
+        .program(PROGRAM)
+        .build()
+}
+
+
+#[test]
+fn entering_an_interrupt_is_a_cycle_of_its_own() {
+    let mut machine = machine();
+    machine.step();
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+
+    // the PSR and the PC to return to go on the supervisor stack, and nothing executes
+    machine.step();
+    assert_eq!(machine.cpu.pc, 0x1000);
+    assert_eq!(machine.cpu.privilege, Privilege::Supervisor);
+    assert_eq!(machine.cpu.priority, PRIORITY);
+    assert_eq!(machine.reg(6), 0x2FFE);
+    assert_eq!(machine.mem(0x2FFE), 0x3001);
+    assert_eq!(machine.mem(0x2FFF) as u16, 0x8001); // user mode, priority 0, CC=P
+    assert_eq!(machine.reg(0), 1);
+    assert_eq!(machine.reg(1), 0);
+    assert!(machine.cpu.interrupts.pending().is_empty());
+
+    // the service routine runs and returns to the interrupted program
+    machine.step();
+    assert_eq!(machine.reg(1), 1);
+    machine.step();
+    assert_eq!(machine.cpu.pc, 0x3001);
+    assert_eq!(machine.cpu.privilege, Privilege::User);
+    assert_eq!(machine.cpu.priority, 0);
+    assert!(machine.cpu.interrupt_taken.is_none());
+
+    machine.run();
+    assert_eq!(machine.reg(0), 3);
+}
+
+#[test]
+fn interrupts_wait_for_a_higher_priority() {
+    let mut machine = machine();
+    machine.cpu.priority = PRIORITY;
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+
+    machine.step();
+    assert_eq!(machine.cpu.pc, 0x3001);
+    assert_eq!(machine.cpu.interrupts.pending().len(), 1);
+}
+
+#[test]
+fn the_keyboard_interrupts_when_a_key_is_typed() {
+    let mut machine = CpuBuilder::new()
+        .program(SERVICE_ROUTINE)
+        .mem(0x0100 + u16::from(KEYBOARD_INTERRUPT_VECTOR), 0x1000)
+        .program(PROGRAM)
+        .build();
+
+    // enabling the interrupt with nothing typed doesn't interrupt
+    machine.cpu.write_memory(KBSR, 0x4000);
+    machine.step();
+    assert_eq!(machine.cpu.pc, 0x3001);
+
+    machine.console.push_input(b"k");
+    machine.step();
+    assert_eq!(machine.cpu.pc, 0x1000);
+    assert_eq!(machine.cpu.priority, KEYBOARD_INTERRUPT_PRIORITY);
+}
+
+#[test]
+fn breakpoints_on_a_service_routine_are_hit() {
+    let mut machine = machine();
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+    let mut debugger = Debugger::new(machine.cpu);
+    debugger.add_breakpoint(0x1000);
+
+    assert_eq!(debugger.continue_execution().unwrap(), StopReason::Breakpoint(0x1000));
+    assert_eq!(debugger.call_depth(), 1);
+
+    // and stepping back leaves the interrupt
+    assert_eq!(debugger.step_back(1), 1);
+    assert_eq!(debugger.cpu.pc, 0x3000);
+    assert_eq!(debugger.cpu.priority, 0);
+    assert_eq!(debugger.call_depth(), 0);
+}
+
+#[test]
+fn next_runs_through_an_interrupt_and_the_instruction() {
+    let mut machine = machine();
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+    let mut debugger = Debugger::new(machine.cpu);
+
+    assert_eq!(debugger.step_over().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3001);
+    assert_eq!(debugger.cpu.reg[0], 1);
+    assert_eq!(debugger.cpu.reg[1], 1);
+}
+
+#[test]
+fn traces_the_interrupt_apart_from_the_instructions() {
+    let mut machine = machine();
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+    let mut debugger = Debugger::new(machine.cpu);
+
+    let trace = SharedBuffer::default();
+    debugger.set_trace(Some(TraceWriter::new(Box::new(trace.clone()), TraceFormat::Text))).unwrap();
+    debugger.step_many(3).unwrap();
+
+    let text = trace.contents();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("x3000  ----  INTERRUPT x81"), "{}", lines[0]);
+    assert!(lines[0].contains("R6=x2FFE"), "{}", lines[0]);
+    assert!(lines[1].starts_with("x1000  1261  ADD R1, R1, #1"), "{}", lines[1]);
+    assert!(lines[2].starts_with("x1001  8000  RTI"), "{}", lines[2]);
+}
+
+#[test]
+fn watchpoints_blame_the_service_routine_not_the_interrupted_instruction() {
+    let mut machine = machine();
+    machine.cpu.interrupts.raise(VECTOR, PRIORITY);
+    let mut debugger = Debugger::new(machine.cpu);
+    debugger.add_watchpoint(Watchpoint { start: 0x2FFE, end: 0x2FFF, kind: WatchKind::Access });
+
+    // pushing onto the stack on the way in isn't an instruction's doing; RTI popping it off is
+    match debugger.continue_execution().unwrap() {
+        StopReason::Watchpoint { pc, ir, .. } => {
+            assert_eq!(pc, 0x1001);
+            assert_eq!(ir, 0x8000);
+        },
+        other => panic!("expected the RTI to hit the watchpoint, got {:?}", other)
+    }
+}
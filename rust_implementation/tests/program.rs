@@ -0,0 +1,82 @@
+// Program files in both formats: hex text and the binary .obj that lc3as
+// writes, read back from files whichever way they're named.
+
+extern crate lc3;
+
+use std::fs;
+use std::path::PathBuf;
+use lc3::{LC3Error, Program};
+
+// A directory of its own for each test, so they can run side by side.
+fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("lc3-program-test-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn program() -> Program {
+    Program::new(0x3000, vec![0x5260, 0x1261, 0xF025])
+}
+
+
+#[test]
+fn writes_obj_files_as_big_endian_words() {
+    assert_eq!(program().to_obj_bytes(), vec![0x30, 0x00, 0x52, 0x60, 0x12, 0x61, 0xF0, 0x25]);
+}
+
+#[test]
+fn reads_back_the_obj_files_it_writes() {
+    let read = Program::from_obj_bytes(&program().to_obj_bytes()).unwrap();
+    assert_eq!(read.program_counter_start(), 0x3000);
+    assert_eq!(read.instructions(), program().instructions());
+
+    // just the origin is an empty program
+    let empty = Program::from_obj_bytes(&[0x40, 0x00]).unwrap();
+    assert_eq!(empty.program_counter_start(), 0x4000);
+    assert!(empty.instructions().is_empty());
+}
+
+#[test]
+fn rejects_obj_files_without_whole_words() {
+    for bytes in &[&[][..], &[0x30][..]] {
+        match Program::from_obj_bytes(bytes) {
+            Err(LC3Error::ObjectFileTruncated) => (),
+            other => panic!("expected {:?} to be truncated, got {:?}", bytes, other.map(|_| ()))
+        }
+    }
+
+    match Program::from_obj_bytes(&[0x30, 0x00, 0x52]) {
+        Err(LC3Error::ObjectFileOddLength(3)) => (),
+        other => panic!("expected an odd length, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn round_trips_through_files_of_either_format() {
+    let directory = test_directory("round-trip");
+
+    for name in &["program.obj", "program.OBJ", "program.hex", "program.txt"] {
+        let path = directory.join(name);
+        program().write_file(&path).unwrap();
+
+        let read = Program::from_file(&path).unwrap();
+        assert_eq!(read.program_counter_start(), 0x3000, "{}", name);
+        assert_eq!(read.instructions(), program().instructions(), "{}", name);
+    }
+
+    assert_eq!(fs::read(directory.join("program.obj")).unwrap(), program().to_obj_bytes());
+    assert_eq!(fs::read_to_string(directory.join("program.hex")).unwrap(), "3000\n5260\n1261\nF025\n");
+}
+
+#[test]
+fn tells_the_formats_apart_by_their_contents() {
+    let directory = test_directory("sniff");
+
+    let hex = directory.join("hex-program");
+    fs::write(&hex, program().to_hex_string()).unwrap();
+    assert_eq!(Program::from_file(&hex).unwrap().instructions(), program().instructions());
+
+    let obj = directory.join("obj-program");
+    fs::write(&obj, program().to_obj_bytes()).unwrap();
+    assert_eq!(Program::from_file(&obj).unwrap().instructions(), program().instructions());
+}
@@ -0,0 +1,266 @@
+// Property tests for the bit selection every decoder is built on, for the
+// instruction formats the assembler produces, and for 16-bit arithmetic.
+
+extern crate lc3;
+extern crate proptest;
+
+mod common;
+
+use common::{execute, CpuBuilder, ORIGIN};
+use lc3::{assemble, disassemble, Address, ConditionCode, Instruction, Word};
+use lc3::utils::{SignedBitSelection, UnsignedBitSelection};
+use proptest::prelude::*;
+
+// Sign extension straight from the definition of two's complement: an n bit
+// field whose top bit is set stands for its unsigned value minus 2^n.
+fn reference_sign_extend(raw: i64, number_of_bits: i32) -> i64 {
+    if raw >= 1 << (number_of_bits - 1) { raw - (1 << number_of_bits) } else { raw }
+}
+
+// Assembles a single instruction at ORIGIN.
+fn encode(line: &str) -> Instruction {
+    let assembly = assemble(&format!(".ORIG x3000\n{}\n.END", line))
+        .unwrap_or_else(|err| panic!("{} should assemble: {}", line, err));
+    assembly.program.instructions()[0]
+}
+
+fn pc_target(offset: i32) -> String {
+    format!("x{:04X}", (ORIGIN + 1).wrapping_add(offset as Address))
+}
+
+// A field selection (left, right) that fits in a word.
+fn selection() -> impl Strategy<Value = (i32, i32)> {
+    (0..16i32).prop_flat_map(|left| (Just(left), 0..=left))
+}
+
+
+//////////////////////////////////////////////////////
+// BIT SELECTION
+//////////////////////////////////////////////////////
+
+
+proptest! {
+    #[test]
+    fn signed_selection_matches_the_reference(value in any::<u16>(), (left, right) in selection()) {
+        prop_assume!(left != right); // single bits are flags, which are never negative
+
+        let raw = i64::from(value.bits(left, right));
+        let expected = reference_sign_extend(raw, left - right + 1);
+        prop_assert_eq!(i64::from(value.bits_signed(left, right)), expected);
+    }
+
+    #[test]
+    fn signed_selection_is_in_range(value in any::<u16>(), (left, right) in selection()) {
+        prop_assume!(left != right);
+
+        let number_of_bits = left - right + 1;
+        let selected = value.bits_signed(left, right);
+        prop_assert!(selected >= -(1 << (number_of_bits - 1)));
+        prop_assert!(selected < 1 << (number_of_bits - 1));
+    }
+
+    #[test]
+    fn signed_and_unsigned_selections_agree_on_the_bits(value in any::<u16>(), (left, right) in selection()) {
+        let mask = (1i64 << (left - right + 1)) - 1;
+        let signed = i64::from(value.bits_signed(left, right));
+        prop_assert_eq!(signed & mask, i64::from(value.bits(left, right)));
+    }
+
+    #[test]
+    fn single_bits_are_never_negative(value in any::<u16>(), bit in 0..16i32) {
+        prop_assert_eq!(value.bits_signed(bit, bit), value.bits(bit, bit));
+    }
+
+    #[test]
+    fn selection_ignores_the_surrounding_bits(field in any::<u16>(), noise in any::<u16>(), (left, right) in selection()) {
+        let field_mask = ((1u32 << (left - right + 1)) - 1) as u16;
+        let field = field & field_mask;
+        let word = (noise & !(field_mask << right)) | (field << right);
+
+        prop_assert_eq!(word.bits(left, right), i32::from(field));
+    }
+
+    #[test]
+    fn selection_is_the_same_for_signed_and_unsigned_words(value in any::<u16>(), (left, right) in selection()) {
+        let signed = value as Word;
+        prop_assert_eq!(signed.bits(left, right), value.bits(left, right));
+        prop_assert_eq!(signed.bits_signed(left, right), value.bits_signed(left, right));
+    }
+}
+
+
+//////////////////////////////////////////////////////
+// INSTRUCTION FORMATS
+//////////////////////////////////////////////////////
+
+
+proptest! {
+    #[test]
+    fn operate_with_registers(add in any::<bool>(), dr in 0..8i32, sr1 in 0..8i32, sr2 in 0..8i32) {
+        let mnemonic = if add { "ADD" } else { "AND" };
+        let line = format!("{} R{}, R{}, R{}", mnemonic, dr, sr1, sr2);
+        let instr = encode(&line);
+
+        prop_assert_eq!(instr.bits(15, 12), if add { 0b0001 } else { 0b0101 });
+        prop_assert_eq!(instr.bits(11, 9), dr);
+        prop_assert_eq!(instr.bits(8, 6), sr1);
+        prop_assert_eq!(instr.bits(5, 3), 0);
+        prop_assert_eq!(instr.bits(2, 0), sr2);
+        prop_assert_eq!(disassemble(instr, ORIGIN), line);
+    }
+
+    #[test]
+    fn operate_with_an_immediate(add in any::<bool>(), dr in 0..8i32, sr1 in 0..8i32, imm5 in -16..16i32) {
+        let mnemonic = if add { "ADD" } else { "AND" };
+        let line = format!("{} R{}, R{}, #{}", mnemonic, dr, sr1, imm5);
+        let instr = encode(&line);
+
+        prop_assert_eq!(instr.bits(11, 9), dr);
+        prop_assert_eq!(instr.bits(8, 6), sr1);
+        prop_assert_eq!(instr.bits(5, 5), 1);
+        prop_assert_eq!(instr.bits_signed(4, 0), imm5);
+        prop_assert_eq!(disassemble(instr, ORIGIN), line);
+    }
+
+    #[test]
+    fn not(dr in 0..8i32, sr in 0..8i32) {
+        let line = format!("NOT R{}, R{}", dr, sr);
+        let instr = encode(&line);
+
+        prop_assert_eq!(instr.bits(15, 12), 0b1001);
+        prop_assert_eq!(instr.bits(11, 9), dr);
+        prop_assert_eq!(instr.bits(8, 6), sr);
+        prop_assert_eq!(instr.bits(5, 0), 0b111111);
+        prop_assert_eq!(disassemble(instr, ORIGIN), line);
+    }
+
+    #[test]
+    fn branch(nzp in 1..8i32, offset in -256..256i32) {
+        let flags: String = [(0b100, 'n'), (0b010, 'z'), (0b001, 'p')].iter()
+            .filter(|&&(bit, _)| nzp & bit != 0)
+            .map(|&(_, flag)| flag)
+            .collect();
+        let instr = encode(&format!("BR{} #{}", flags, offset));
+
+        prop_assert_eq!(instr.bits(15, 12), 0b0000);
+        prop_assert_eq!(instr.bits(11, 9), nzp);
+        prop_assert_eq!(instr.bits_signed(8, 0), offset);
+        prop_assert!(disassemble(instr, ORIGIN).ends_with(&pc_target(offset)));
+    }
+
+    #[test]
+    fn pc_relative(which in 0..5usize, dr in 0..8i32, offset in -256..256i32) {
+        let (mnemonic, op_code) = [("LD", 0b0010), ("LDI", 0b1010), ("LEA", 0b1110), ("ST", 0b0011), ("STI", 0b1011)][which];
+        let instr = encode(&format!("{} R{}, #{}", mnemonic, dr, offset));
+
+        prop_assert_eq!(instr.bits(15, 12), op_code);
+        prop_assert_eq!(instr.bits(11, 9), dr);
+        prop_assert_eq!(instr.bits_signed(8, 0), offset);
+        prop_assert_eq!(disassemble(instr, ORIGIN), format!("{} R{}, {}", mnemonic, dr, pc_target(offset)));
+    }
+
+    #[test]
+    fn base_plus_offset(load in any::<bool>(), dr in 0..8i32, base in 0..8i32, offset in -32..32i32) {
+        let mnemonic = if load { "LDR" } else { "STR" };
+        let line = format!("{} R{}, R{}, #{}", mnemonic, dr, base, offset);
+        let instr = encode(&line);
+
+        prop_assert_eq!(instr.bits(15, 12), if load { 0b0110 } else { 0b0111 });
+        prop_assert_eq!(instr.bits(11, 9), dr);
+        prop_assert_eq!(instr.bits(8, 6), base);
+        prop_assert_eq!(instr.bits_signed(5, 0), offset);
+        prop_assert_eq!(disassemble(instr, ORIGIN), line);
+    }
+
+    #[test]
+    fn jsr(offset in -1024..1024i32) {
+        let instr = encode(&format!("JSR #{}", offset));
+
+        prop_assert_eq!(instr.bits(15, 12), 0b0100);
+        prop_assert_eq!(instr.bits(11, 11), 1);
+        prop_assert_eq!(instr.bits_signed(10, 0), offset);
+        prop_assert_eq!(disassemble(instr, ORIGIN), format!("JSR {}", pc_target(offset)));
+    }
+
+    #[test]
+    fn register_jumps(jsrr in any::<bool>(), base in 0..8i32) {
+        let mnemonic = if jsrr { "JSRR" } else { "JMP" };
+        let instr = encode(&format!("{} R{}", mnemonic, base));
+
+        prop_assert_eq!(instr.bits(15, 12), if jsrr { 0b0100 } else { 0b1100 });
+        prop_assert_eq!(instr.bits(11, 9), 0);
+        prop_assert_eq!(instr.bits(8, 6), base);
+        prop_assert_eq!(instr.bits(5, 0), 0);
+    }
+
+    #[test]
+    fn trap(trap_vector in 0..256i32) {
+        let instr = encode(&format!("TRAP x{:02X}", trap_vector));
+
+        prop_assert_eq!(instr.bits(15, 12), 0b1111);
+        prop_assert_eq!(instr.bits(11, 8), 0);
+        prop_assert_eq!(instr.bits(7, 0), trap_vector);
+    }
+}
+
+
+//////////////////////////////////////////////////////
+// ARITHMETIC
+//////////////////////////////////////////////////////
+
+
+proptest! {
+    #[test]
+    fn add_wraps_at_16_bits(a in any::<Word>(), b in any::<Word>()) {
+        // ADD R0, R1, R2
+        let machine = execute(0x1042, CpuBuilder::new().reg(1, a).reg(2, b));
+        let sum = a.wrapping_add(b);
+
+        prop_assert_eq!(machine.reg(0), sum);
+        prop_assert_eq!(i32::from(machine.reg(0)), (i32::from(a) + i32::from(b)) as Word as i32);
+        prop_assert_eq!(machine.cpu.cc, ConditionCode::from_value(sum));
+    }
+
+    #[test]
+    fn add_immediate_wraps_at_16_bits(a in any::<Word>(), imm5 in -16..16i32) {
+        // ADD R0, R1, #imm5
+        let instr = 0x1060 | (imm5 as Instruction & 0x1F);
+        let machine = execute(instr, CpuBuilder::new().reg(1, a));
+
+        prop_assert_eq!(machine.reg(0), a.wrapping_add(imm5 as Word));
+    }
+
+    #[test]
+    fn not_is_its_own_inverse(a in any::<Word>()) {
+        // NOT R0, R1 then NOT R0, R0
+        let mut machine = CpuBuilder::new().reg(1, a).instruction(0x907F).build();
+        machine.step();
+        prop_assert_eq!(machine.reg(0), !a);
+        prop_assert_eq!(machine.reg(0), a.wrapping_neg().wrapping_sub(1));
+
+        machine.cpu.mem[ORIGIN + 1] = 0x903Fu16 as Word;
+        machine.step();
+        prop_assert_eq!(machine.reg(0), a);
+    }
+
+    #[test]
+    fn and_matches_bitwise_and(a in any::<Word>(), b in any::<Word>()) {
+        // AND R0, R1, R2
+        let machine = execute(0x5042, CpuBuilder::new().reg(1, a).reg(2, b));
+
+        prop_assert_eq!(machine.reg(0), a & b);
+        prop_assert_eq!(machine.cpu.cc, ConditionCode::from_value(a & b));
+    }
+
+    #[test]
+    fn negation_by_not_and_add(a in any::<Word>()) {
+        // NOT R0, R1 then ADD R0, R0, #1: two's complement negation, which
+        // leaves -32768 as it is
+        let mut machine = CpuBuilder::new().reg(1, a).instruction(0x907F).build();
+        machine.cpu.mem[ORIGIN + 1] = 0x1021;
+        machine.step();
+        machine.step();
+
+        prop_assert_eq!(machine.reg(0), a.wrapping_neg());
+    }
+}
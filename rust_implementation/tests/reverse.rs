@@ -0,0 +1,103 @@
+// Stepping backwards: how much history is kept, reverse continuing to a
+// breakpoint, and what happens to the keyboard's input when a read is undone.
+
+extern crate lc3;
+
+mod common;
+
+use common::CpuBuilder;
+use lc3::{Debugger, StopReason};
+
+const COUNTING: &str = r#"
+        .ORIG x3000
+        ADD R0, R0, #1      ; x3000
+        ADD R0, R0, #1      ; x3001
+        ADD R0, R0, #1      ; x3002
+        ADD R0, R0, #1      ; x3003
+        ADD R0, R0, #1      ; x3004
+        HALT                ; x3005
+        .END
+"#;
+
+// Reads a key by polling and then reads the next one with GETC.
+const KEYS: &str = r#"
+        .ORIG x3000
+KEY     LDI R1, KBSRP       ; x3000
+        BRzp KEY            ; x3001
+        LDI R0, KBDRP       ; x3002
+        GETC                ; x3003
+        HALT                ; x3004
+KBSRP   .FILL xFE00
+KBDRP   .FILL xFE02
+        .END
+"#;
+
+fn debugger(source: &str, input: &[u8]) -> Debugger {
+    Debugger::new(CpuBuilder::new().program(source).input(input).build().cpu)
+}
+
+
+#[test]
+fn the_history_size_drops_the_oldest_records() {
+    let mut debugger = debugger(COUNTING, b"");
+    debugger.set_history_size(3);
+    assert_eq!(debugger.step_many(5).unwrap(), None);
+    assert_eq!(debugger.history_len(), 3);
+
+    // only the last three instructions can be undone
+    assert_eq!(debugger.step_back(5), 3);
+    assert_eq!(debugger.cpu.pc, 0x3002);
+    assert_eq!(debugger.cpu.reg[0], 2);
+
+    // shrinking it drops the oldest of what's left
+    debugger.step_many(3).unwrap();
+    debugger.set_history_size(1);
+    assert_eq!(debugger.history_len(), 1);
+    assert_eq!(debugger.step_back(2), 1);
+    assert_eq!(debugger.cpu.pc, 0x3004);
+}
+
+#[test]
+fn reverse_continue_stops_at_a_breakpoint() {
+    let mut debugger = debugger(COUNTING, b"");
+    debugger.add_breakpoint(0x3001);
+    assert_eq!(debugger.step_many(4).unwrap(), None);
+
+    assert_eq!(debugger.reverse_continue(), StopReason::Breakpoint(0x3001));
+    assert_eq!(debugger.cpu.pc, 0x3001);
+    assert_eq!(debugger.cpu.reg[0], 1);
+
+    // and runs out of history past it
+    assert_eq!(debugger.reverse_continue(), StopReason::StartOfHistory);
+    assert_eq!(debugger.cpu.pc, 0x3000);
+}
+
+#[test]
+fn stepping_back_over_a_keyboard_read_gives_the_key_back() {
+    let mut debugger = debugger(KEYS, b"ab");
+    debugger.step_many(3).unwrap();
+    assert_eq!(debugger.cpu.reg[0], 'a' as i16);
+    assert_eq!(debugger.cpu.mem.peek(0xFE00), 0);
+
+    debugger.step_back(1);
+    assert_eq!(debugger.cpu.pc, 0x3002);
+    assert_eq!(debugger.cpu.mem.peek(0xFE00) as u16, 0x8000);
+    assert_eq!(debugger.cpu.mem.peek(0xFE02), 'a' as i16);
+
+    // reading it again gets the same key
+    debugger.step().unwrap();
+    assert_eq!(debugger.cpu.reg[0], 'a' as i16);
+}
+
+#[test]
+fn native_getc_cant_be_stepped_back_over() {
+    let mut debugger = debugger(KEYS, b"ab");
+    debugger.step_many(3).unwrap();
+
+    // GETC takes b from the console itself, which can't be given it back
+    debugger.step().unwrap();
+    assert_eq!(debugger.cpu.reg[0], 'b' as i16);
+    assert_eq!(debugger.history_len(), 0);
+    assert_eq!(debugger.step_back(1), 0);
+    assert_eq!(debugger.cpu.pc, 0x3004);
+}
@@ -0,0 +1,109 @@
+// Stepping over calls and out of subroutines, with both native traps and the
+// bundled operating system's service routines.
+
+extern crate lc3;
+
+mod common;
+
+use common::CpuBuilder;
+use lc3::{Address, Debugger, StopReason};
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R0, R0, #0      ; x3000
+        JSR OUTER           ; x3001
+        LEA R0, TEXT        ; x3002
+        PUTS                ; x3003
+        HALT                ; x3004
+OUTER   ST R7, SAVE         ; x3005
+        JSR INNER           ; x3006
+        LD R7, SAVE         ; x3007
+        RET                 ; x3008
+INNER   ADD R0, R0, #5      ; x3009
+        ADD R0, R0, R0      ; x300A
+        RET                 ; x300B
+SAVE    .BLKW 1
+TEXT    .STRINGZ "ok"
+        .END
+"#;
+
+// Loading the program moves the PC to its origin, so it's set afterwards.
+fn debugger(pc: Address) -> Debugger {
+    let mut cpu = CpuBuilder::new().program(PROGRAM).build().cpu;
+    cpu.pc = pc;
+    Debugger::new(cpu)
+}
+
+
+#[test]
+fn next_runs_a_whole_subroutine() {
+    let mut debugger = debugger(0x3000);
+
+    assert_eq!(debugger.step_over().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3001);
+
+    // over the JSR and everything it calls
+    assert_eq!(debugger.step_over().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3002);
+    assert_eq!(debugger.cpu.reg[0], 10);
+}
+
+#[test]
+fn next_runs_a_whole_trap_service_routine() {
+    let mut machine = CpuBuilder::with_operating_system().program(PROGRAM).build();
+    let console = machine.console.clone();
+    machine.cpu.pc = 0x3003;
+    machine.cpu.reg[0] = 0x300D; // TEXT
+    let mut debugger = Debugger::new(machine.cpu);
+
+    assert_eq!(debugger.step_over().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3004);
+    assert_eq!(console.output_string(), "ok");
+}
+
+#[test]
+fn next_stops_at_breakpoints_inside_the_call() {
+    let mut debugger = debugger(0x3001);
+    debugger.add_breakpoint(0x300A);
+
+    assert_eq!(debugger.step_over().unwrap(), StopReason::Breakpoint(0x300A));
+    assert_eq!(debugger.step_over().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x300B);
+}
+
+#[test]
+fn next_reports_the_halt() {
+    let mut debugger = debugger(0x3004);
+
+    assert_eq!(debugger.step_over().unwrap(), StopReason::Halted);
+}
+
+#[test]
+fn finish_returns_to_the_caller() {
+    let mut debugger = debugger(0x3000);
+    debugger.add_breakpoint(0x3009);
+
+    assert_eq!(debugger.continue_execution().unwrap(), StopReason::Breakpoint(0x3009));
+    assert_eq!(debugger.call_depth(), 2);
+
+    // out of INNER, then out of OUTER
+    assert_eq!(debugger.step_out().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3007);
+    assert_eq!(debugger.step_out().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3002);
+    assert_eq!(debugger.call_depth(), 0);
+}
+
+#[test]
+fn finished_subroutines_can_be_stepped_back_through() {
+    let mut debugger = debugger(0x3006);
+    debugger.step().unwrap();
+
+    assert_eq!(debugger.step_out().unwrap(), StopReason::StepComplete);
+    assert_eq!(debugger.cpu.pc, 0x3007);
+
+    // finishing is made of ordinary steps, so they can be undone one by one
+    assert_eq!(debugger.step_back(3), 3);
+    assert_eq!(debugger.cpu.pc, 0x3009);
+    assert_eq!(debugger.call_depth(), 1);
+}
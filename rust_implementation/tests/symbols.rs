@@ -0,0 +1,117 @@
+// Symbol tables from the assembler and from lc3as .sym files, and how
+// addresses are read and shown with them.
+
+extern crate lc3;
+
+use lc3::{assemble, disassemble_with_symbols, Symbols};
+use lc3::disassembler::disassemble_program;
+use lc3::symbols::describe_address;
+use lc3::utils::parse_address;
+
+// As lc3as writes it, tabs and all.
+const SYM_FILE: &str = "// Symbol table\n\
+// Scope level 0:\n\
+//\tSymbol Name       Page Address\n\
+//\t----------------  ------------\n\
+//\tMAIN              3000\n\
+//\tLOOP              3002\n\
+//\tRESULT            3010\n\
+\n";
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+MAIN    AND R0, R0, #0
+        ADD R1, R0, #3
+LOOP    ADD R0, R0, #2
+        ADD R1, R1, #-1
+        BRp LOOP
+        ST R0, RESULT
+        HALT
+RESULT  .BLKW 1
+        .END
+"#;
+
+fn args(text: &str) -> Vec<String> {
+    text.split_whitespace().map(ToOwned::to_owned).collect()
+}
+
+
+#[test]
+fn reads_lc3as_symbol_files() {
+    let symbols = Symbols::from_sym_string(SYM_FILE);
+
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.address("MAIN"), Some(0x3000));
+    assert_eq!(symbols.address("LOOP"), Some(0x3002));
+    assert_eq!(symbols.address("RESULT"), Some(0x3010));
+    assert_eq!(symbols.address("Symbol"), None);
+}
+
+#[test]
+fn uses_the_assemblers_symbol_table() {
+    let assembly = assemble(PROGRAM).unwrap();
+    let symbols = Symbols::from_table(&assembly.symbols);
+
+    assert_eq!(symbols.address("LOOP"), Some(0x3002));
+    assert_eq!(symbols.address("RESULT"), Some(0x3007));
+}
+
+#[test]
+fn describes_addresses_by_the_closest_label() {
+    let symbols = Symbols::from_sym_string(SYM_FILE);
+
+    assert_eq!(symbols.describe(0x3000), Some("MAIN".to_owned()));
+    assert_eq!(symbols.describe(0x3001), Some("MAIN+1".to_owned()));
+    assert_eq!(symbols.describe(0x3005), Some("LOOP+3".to_owned()));
+    assert_eq!(symbols.describe(0x2FFF), None);
+    assert_eq!(symbols.describe(0x4000), None); // too far from RESULT to belong to it
+
+    assert_eq!(describe_address(0x3003, &symbols), "x3003 (LOOP+1)");
+    assert_eq!(describe_address(0x2000, &symbols), "x2000");
+}
+
+#[test]
+fn prefers_one_label_per_address() {
+    let mut symbols = Symbols::new();
+    symbols.insert("START", 0x3000);
+    symbols.insert("BEGIN", 0x3000);
+    assert_eq!(symbols.describe(0x3000), Some("BEGIN".to_owned()));
+
+    // moving a label lets the other one stand in for its old address
+    symbols.insert("BEGIN", 0x3100);
+    assert_eq!(symbols.describe(0x3000), Some("START".to_owned()));
+    assert_eq!(symbols.describe(0x3100), Some("BEGIN".to_owned()));
+}
+
+#[test]
+fn parses_labels_and_offsets_as_addresses() {
+    let mut symbols = Symbols::from_sym_string(SYM_FILE);
+    symbols.insert("ADD", 0x3100);
+
+    assert_eq!(parse_address(&args("b LOOP"), 1, &symbols).unwrap(), 0x3002);
+    assert_eq!(parse_address(&args("b LOOP+2"), 1, &symbols).unwrap(), 0x3004);
+    assert_eq!(parse_address(&args("b RESULT-x10"), 1, &symbols).unwrap(), 0x3000);
+    assert_eq!(parse_address(&args("b x3005"), 1, &symbols).unwrap(), 0x3005);
+    assert_eq!(parse_address(&args("b 0x3005"), 1, &symbols).unwrap(), 0x3005);
+
+    // a label wins over bare hex spelled the same way
+    assert_eq!(parse_address(&args("b ADD"), 1, &symbols).unwrap(), 0x3100);
+    assert_eq!(parse_address(&args("b BEEF"), 1, &symbols).unwrap(), 0xBEEF);
+
+    assert!(parse_address(&args("b NOWHERE"), 1, &symbols).is_err());
+    assert!(parse_address(&args("b"), 1, &symbols).is_err());
+}
+
+#[test]
+fn annotates_disassembly() {
+    let assembly = assemble(PROGRAM).unwrap();
+    let symbols = Symbols::from_table(&assembly.symbols);
+
+    // BRp LOOP at x3004
+    assert_eq!(disassemble_with_symbols(0x03FD, 0x3004, &symbols), "BRp x3002 (LOOP)");
+
+    let lines = disassemble_program(&assembly.program, &symbols);
+    assert_eq!(lines[0], "x3000 (MAIN): x5020    AND R0, R0, #0");
+    assert_eq!(lines[5], "x3005 (LOOP+3): x3001    ST R0, x3007 (RESULT)");
+    assert_eq!(lines[6], "x3006 (LOOP+4): xF025    HALT");
+}
@@ -0,0 +1,101 @@
+// Execution traces: one line per instruction in text or JSON Lines, with the
+// registers each instruction wrote and the memory it touched.
+
+extern crate lc3;
+#[macro_use] extern crate serde_json;
+
+mod common;
+
+use common::{CpuBuilder, SharedBuffer};
+use lc3::{Debugger, TraceFormat, TraceWriter};
+use serde_json::Value;
+
+const PROGRAM: &str = r#"
+        .ORIG x3000
+        AND R1, R1, #0      ; x3000
+        ADD R1, R1, #0      ; x3001
+        ST R1, VALUE        ; x3002
+        LD R2, VALUE        ; x3003
+        JSR SUB             ; x3004
+        HALT                ; x3005
+SUB     RET                 ; x3006
+VALUE   .FILL #5            ; x3007
+        .END
+"#;
+
+// The trace of the whole program, run with native traps.
+fn trace(format: TraceFormat) -> Vec<String> {
+    let mut debugger = Debugger::new(CpuBuilder::new().program(PROGRAM).build().cpu);
+    let trace = SharedBuffer::default();
+    debugger.set_trace(Some(TraceWriter::new(Box::new(trace.clone()), format))).unwrap();
+    debugger.continue_execution().unwrap();
+    debugger.set_trace(None).unwrap();
+
+    trace.contents().lines().map(ToOwned::to_owned).collect()
+}
+
+
+#[test]
+fn traces_each_instruction_as_text() {
+    let lines = trace(TraceFormat::Text);
+    assert_eq!(lines.len(), 7);
+
+    assert_eq!(lines[0], "x3000  5260  AND R1, R1, #0            R1=x0000  CC=Z");
+    assert_eq!(lines[2], "x3002  3204  ST R1, x3007              wrote x3007=x0000 (was x0005)  CC=Z");
+    assert_eq!(lines[3], "x3003  2403  LD R2, x3007              R2=x0000  read x3007=x0000  CC=Z");
+    assert!(lines[4].starts_with("x3004  4801  JSR x3006"), "{}", lines[4]);
+    assert!(lines[4].contains("  R7=x3005  "), "{}", lines[4]);
+    assert!(lines[5].starts_with("x3006  C1C0  RET"), "{}", lines[5]);
+    assert!(lines[6].starts_with("x3005  F025  HALT"), "{}", lines[6]);
+}
+
+#[test]
+fn registers_written_are_traced_even_when_unchanged() {
+    let lines = trace(TraceFormat::Text);
+
+    // ADD R1, R1, #0 leaves R1 as it was, and R2 was already zero before the LD
+    assert!(lines[1].contains("  R1=x0000  "), "{}", lines[1]);
+    assert!(lines[3].contains("  R2=x0000  "), "{}", lines[3]);
+
+    // and registers that only happen to hold a value aren't listed against a store
+    assert!(!lines[2].contains("R1="), "{}", lines[2]);
+}
+
+#[test]
+fn traces_each_instruction_as_json_lines() {
+    let lines = trace(TraceFormat::JsonLines);
+    assert_eq!(lines.len(), 7);
+
+    let entries: Vec<Value> = lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(entries[2], json!({
+        "pc": 12290, "ir": 12804, "asm": "ST R1, x3007", "registers": {},
+        "reads": [], "writes": [{ "address": 12295, "value": 0, "previous": 5 }], "cc": "Z"
+    }));
+    assert_eq!(entries[3], json!({
+        "pc": 12291, "ir": 9219, "asm": "LD R2, x3007", "registers": { "R2": 0 },
+        "reads": [{ "address": 12295, "value": 0 }], "writes": [], "cc": "Z"
+    }));
+    assert_eq!(entries[4]["registers"], json!({ "R7": 12293 }));
+}
+
+#[test]
+fn picks_the_format_from_the_file_name() {
+    assert_eq!(TraceFormat::for_file("run.jsonl"), TraceFormat::JsonLines);
+    assert_eq!(TraceFormat::for_file("run.JSON"), TraceFormat::JsonLines);
+    assert_eq!(TraceFormat::for_file("run.txt"), TraceFormat::Text);
+    assert_eq!(TraceFormat::for_file("run"), TraceFormat::Text);
+}
+
+#[test]
+fn traps_into_the_operating_system_write_r7_and_the_stack_pointer() {
+    let mut machine = CpuBuilder::with_operating_system().program(".ORIG x3000\nOUT\nHALT\n.END").build();
+    machine.cpu.reg[0] = 'k' as i16;
+    let mut debugger = Debugger::new(machine.cpu);
+    let trace = SharedBuffer::default();
+    debugger.set_trace(Some(TraceWriter::new(Box::new(trace.clone()), TraceFormat::Text))).unwrap();
+    debugger.step().unwrap();
+
+    let text = trace.contents();
+    assert!(text.starts_with("x3000  F021  OUT"), "{}", text);
+    assert!(text.contains("  R6=x2FFE  R7=x3001  "), "{}", text);
+}
@@ -0,0 +1,206 @@
+// Every trap, both as handled natively and by the bundled operating system.
+
+extern crate lc3;
+
+mod common;
+
+use common::{CpuBuilder, Machine, ORIGIN};
+use lc3::{LC3Error, Privilege, Word};
+
+const GETC: &str = ".ORIG x3000\nGETC\nHALT\n.END";
+const OUT: &str = ".ORIG x3000\nLD R0, CHAR\nOUT\nHALT\nCHAR .FILL x41\n.END";
+const PUTS: &str = ".ORIG x3000\nLEA R0, TEXT\nPUTS\nHALT\nTEXT .STRINGZ \"hello\\n\"\n.END";
+const IN: &str = ".ORIG x3000\nIN\nHALT\n.END";
+
+// The operating system's HALT stops the clock partway through its routine, so
+// these keep what they want checked in memory rather than in registers.
+const SAVED_GETC: &str = ".ORIG x3000\nGETC\nST R0, RESULT\nHALT\nRESULT .BLKW 1\n.END";
+const SAVED_IN: &str = ".ORIG x3000\nIN\nST R0, RESULT\nHALT\nRESULT .BLKW 1\n.END";
+const HALT: &str = ".ORIG x3000\nHALT\nADD R1, R1, #1\n.END";
+
+// "LC-3!" packed two characters per word, low byte first
+const PUTSP: &str = ".ORIG x3000\nLEA R0, TEXT\nPUTSP\nHALT\nTEXT .FILL x434C\n.FILL x332D\n.FILL x0021\n.END";
+
+fn native(source: &str, input: &[u8]) -> Machine {
+    let mut machine = CpuBuilder::new().program(source).input(input).build();
+    machine.run();
+    machine
+}
+
+fn with_operating_system(source: &str, input: &[u8]) -> Machine {
+    let mut machine = CpuBuilder::with_operating_system().program(source).input(input).build();
+    machine.run();
+    machine
+}
+
+
+//////////////////////////////////////////////////////
+// NATIVE TRAPS
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn native_getc_reads_a_character_into_r0() {
+    let machine = native(GETC, b"z");
+    assert_eq!(machine.reg(0), Word::from(b'z'));
+    assert_eq!(machine.output(), "");
+}
+
+#[test]
+fn native_getc_fails_without_input() {
+    let mut machine = CpuBuilder::new().program(GETC).build();
+    match machine.cpu.run_one_instruction_cycle() {
+        Err(LC3Error::EndOfInput) => {},
+        other => panic!("expected the input to run out, got {:?}", other.err())
+    }
+}
+
+#[test]
+fn native_out_writes_r0() {
+    assert!(native(OUT, b"").output().starts_with("A"));
+}
+
+#[test]
+fn native_puts_writes_one_character_per_word() {
+    assert!(native(PUTS, b"").output().starts_with("hello\n"));
+}
+
+#[test]
+fn native_in_prompts_and_reads_a_character() {
+    let machine = native(IN, b"q");
+    assert!(machine.output().starts_with("Enter a character: "));
+    assert_eq!(machine.reg(0), Word::from(b'q'));
+}
+
+#[test]
+fn native_putsp_writes_two_characters_per_word() {
+    assert_eq!(native(PUTSP, b"").output(), "LC-3!");
+}
+
+#[test]
+fn native_puts_stops_at_the_top_of_memory_without_a_terminator() {
+    for &trap in &[0xF022, 0xF024] {
+        let mut builder = CpuBuilder::new().instruction(trap).reg(0, 0xFFFCu16 as Word);
+        for addr in 0xFFFC..=0xFFFF {
+            builder = builder.mem(addr, 0x4141);
+        }
+
+        let mut machine = builder.build();
+        match machine.cpu.run_one_instruction_cycle() {
+            Err(LC3Error::UnterminatedString(0xFFFC)) => {},
+            other => panic!("expected TRAP x{:X} to find no terminator, got {:?}", trap & 0xFF, other.err())
+        }
+        assert_eq!(machine.output(), "");
+    }
+}
+
+#[test]
+fn native_halt_stops_the_cpu() {
+    let machine = native(HALT, b"");
+    assert!(!machine.cpu.running);
+    assert_eq!(machine.cpu.pc, ORIGIN + 1);
+    assert_eq!(machine.reg(1), 0);
+}
+
+#[test]
+fn native_unknown_traps_are_rejected() {
+    // TRAP x26
+    let mut machine = CpuBuilder::new().instruction(0xF026).build();
+    match machine.cpu.run_one_instruction_cycle() {
+        Err(LC3Error::UnsupportedTrapCode(0x26)) => {},
+        other => panic!("expected an unsupported trap code, got {:?}", other.err())
+    }
+}
+
+
+//////////////////////////////////////////////////////
+// OPERATING SYSTEM TRAPS
+//////////////////////////////////////////////////////
+
+
+#[test]
+fn trap_goes_through_the_trap_vector_table() {
+    // TRAP x21
+    let mut machine = CpuBuilder::with_operating_system().instruction(0xF021).reg(6, 0x4000).build();
+    machine.step();
+
+    let routine = machine.mem(0x0021) as u16;
+    assert_ne!(routine, 0);
+    assert_eq!(machine.cpu.pc, routine);
+    assert_eq!(machine.reg(7), (ORIGIN + 1) as Word);
+    assert_eq!(machine.cpu.privilege, Privilege::Supervisor);
+    assert_eq!(machine.cpu.saved_usp, 0x4000);
+}
+
+#[test]
+fn getc_returns_to_the_user_program() {
+    let machine = with_operating_system(SAVED_GETC, b"z");
+    assert_eq!(machine.mem(ORIGIN + 3), Word::from(b'z'));
+    assert_eq!(machine.cpu.privilege, Privilege::Supervisor); // stopped inside HALT
+    assert!(machine.output().contains("Halting the LC-3"));
+}
+
+#[test]
+fn out_writes_r0() {
+    assert!(with_operating_system(OUT, b"").output().starts_with("A"));
+}
+
+#[test]
+fn puts_writes_one_character_per_word() {
+    assert!(with_operating_system(PUTS, b"").output().starts_with("hello\n"));
+}
+
+#[test]
+fn in_prompts_echoes_and_reads_a_character() {
+    let machine = with_operating_system(SAVED_IN, b"q");
+    assert!(machine.output().starts_with("\nInput a character> q\n"));
+    assert_eq!(machine.mem(ORIGIN + 3), Word::from(b'q'));
+}
+
+#[test]
+fn putsp_writes_two_characters_per_word() {
+    assert!(with_operating_system(PUTSP, b"").output().starts_with("LC-3!\n"));
+}
+
+#[test]
+fn halt_stops_the_clock_and_can_be_resumed() {
+    let mut machine = with_operating_system(HALT, b"");
+    assert!(!machine.cpu.running);
+    assert!(machine.output().ends_with("\n\n--- Halting the LC-3 ---\n\n"));
+
+    // restarting the clock finishes the routine and returns after the HALT
+    let pc = machine.cpu.pc;
+    machine.cpu.set_pc_address(pc);
+    for _ in 0..100 {
+        if machine.cpu.pc == ORIGIN + 1 { break }
+        machine.step();
+    }
+    assert_eq!(machine.cpu.pc, ORIGIN + 1);
+    assert_eq!(machine.cpu.privilege, Privilege::User);
+    machine.step();
+    assert_eq!(machine.reg(1), 1);
+}
+
+#[test]
+fn traps_preserve_registers() {
+    let machine = with_operating_system(r#"
+        .ORIG x3000
+        LD R1, ONE
+        LD R2, TWO
+        LD R3, THREE
+        LEA R0, TEXT
+        PUTSP
+        ST R1, ONE
+        ST R2, TWO
+        ST R3, THREE
+        HALT
+ONE     .FILL 1
+TWO     .FILL 2
+THREE   .FILL 3
+TEXT    .FILL x6261
+        .FILL x0063
+        .END
+    "#, b"");
+    assert!(machine.output().starts_with("abc"));
+    assert_eq!((machine.mem(ORIGIN + 9), machine.mem(ORIGIN + 10), machine.mem(ORIGIN + 11)), (1, 2, 3));
+}